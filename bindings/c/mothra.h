@@ -14,19 +14,55 @@ extern "C" {
 #endif
 
 EXPORT void network_start(char**, int, char**, int);
-EXPORT void send_gossip(unsigned char*, int, unsigned char*, int);
+// Returns the id the publish result callback receives the result of the message with.
+EXPORT unsigned long long send_gossip(unsigned char*, int, unsigned char*, int);
+EXPORT void validate_gossip_message(unsigned char*, int, unsigned char*, int, unsigned char);
 EXPORT void send_rpc_request(unsigned char*, int, unsigned char*, int, unsigned char*, int);
 EXPORT void send_rpc_response(unsigned char*, int, unsigned char*, int, unsigned char*, int);
+// Rejects the request the RPC handler received with the given request index.
+EXPORT void send_rpc_error_response(unsigned char*, int, unsigned char*, int, unsigned char, unsigned char, unsigned char*, int);
+EXPORT void dial_peer(unsigned char*, int);
+EXPORT void disconnect_peer(unsigned char*, int, unsigned long long);
+EXPORT void ban_peer(unsigned char*, int, unsigned long long);
+EXPORT void unban_peer(unsigned char*, int);
+EXPORT void add_peer_filter_entry(unsigned char, unsigned char*, int);
+EXPORT void remove_peer_filter_entry(unsigned char, unsigned char*, int);
 
+// Call the visitor with each connected peer subscribed to the topic and return their number.
+EXPORT int topic_peers(unsigned char*, int, void (*visit_peer_ptr)(const unsigned char*, int));
+// Call the visitor with each connected peer subscribed to the topic that forwarded a message on it
+// within the last minute and return their number. This approximates the gossipsub mesh, which is
+// not exposed: peers answering gossip requests may appear and quiet mesh peers may be missing.
+EXPORT int active_topic_peers(unsigned char*, int, void (*visit_peer_ptr)(const unsigned char*, int));
+
+// The gossip handler receives the message id, the peer that forwarded the message, the peer that
+// published it (empty unless it signed the message), the sequence number, the topics and the data.
+// The sequence number is not covered by the signature, and a signed message can be replayed once
+// gossipsub forgot its id.
 EXPORT void register_handlers(
    void (*discovered_peer_ptr)(const unsigned char*, int), 
-   void (*receive_gossip_ptr)(const unsigned char*, int, const unsigned char*, int, const unsigned char*, int, unsigned char*, int), 
+   void (*receive_gossip_ptr)(const unsigned char*, int, const unsigned char*, int, const unsigned char*, int, unsigned long long, const unsigned char**, const int*, int, unsigned char*, int), 
    void (*receive_rpc_ptr)(const unsigned char*, int, int, const unsigned char*, int, unsigned char*, int)
 );
+
+// The callback must return a non-zero value to accept the connection. It is called from the
+// network threads once per established connection, including the ones to discovered peers.
+EXPORT void register_connection_gate(
+   int (*accept_connection_ptr)(const unsigned char*, int, const unsigned char*, int, int, const unsigned char*, int)
+);
+
+// The callback receives the id returned by send_gossip and an upper bound of the number of peers
+// the message was sent to: the connected peers subscribed to the topic, capped at the mesh size.
+// Otherwise it receives a negative error code: -1 if no peer is subscribed to the topic, -2 for a
+// duplicate message, -3 for a message that is too large, -4 if the message could not be encoded
+// and -5 if the network service stopped. It is called from the network threads.
+EXPORT void register_publish_result_handler(
+   void (*publish_result_ptr)(unsigned long long, int)
+);
        
 // Events functions called by Core
 EXPORT void discovered_peer(const unsigned char*, int);
-EXPORT void receive_gossip(const unsigned char*, int, const unsigned char*, int, const unsigned char*, int, unsigned char*, int);
+EXPORT void receive_gossip(const unsigned char*, int, const unsigned char*, int, const unsigned char*, int, unsigned long long, const unsigned char**, const int*, int, unsigned char*, int);
 EXPORT void receive_rpc(const unsigned char*, int, int, const unsigned char*, int, unsigned char*, int);
 
 #ifdef __cplusplus
@@ -26,7 +26,10 @@ namespace Bindings
         public static extern unsafe void Start([In, Out] string[] clientConstants, int numClientConstants, [In, Out] string[] args, int numArgs);
 
         [DllImport(DllName, EntryPoint = "send_gossip", CallingConvention = CallingConvention.Cdecl)]
-        public static extern unsafe void SendGossip(byte* topicUtf8, int topicLength, byte* data, int dataLength);
+        public static extern unsafe ulong SendGossip(byte* topicUtf8, int topicLength, byte* data, int dataLength);
+
+        [DllImport(DllName, EntryPoint = "validate_gossip_message", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void ValidateGossipMessage(byte* messageIdUtf8, int messageIdLength, byte* peerUtf8, int peerLength, byte valid);
 
         [DllImport(DllName, EntryPoint = "send_rpc_request", CallingConvention = CallingConvention.Cdecl)]
         public static extern unsafe void SendRequest(byte* methodUtf8, int methodLength, byte* peerUtf8, int peerLength, byte* data, int dataLength);
@@ -34,16 +37,60 @@ namespace Bindings
         [DllImport(DllName, EntryPoint = "send_rpc_response", CallingConvention = CallingConvention.Cdecl)]
         public static extern unsafe void SendResponse(byte* methodUtf8, int methodLength, byte* peerUtf8, int peerLength, byte* data, int dataLength);
 
+        [DllImport(DllName, EntryPoint = "send_rpc_error_response", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void SendErrorResponse(byte* methodUtf8, int methodLength, byte* peerUtf8, int peerLength, byte requestIndex, byte errorCode, byte* reasonUtf8, int reasonLength);
+
+        [DllImport(DllName, EntryPoint = "dial_peer", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void DialPeer(byte* addressUtf8, int addressLength);
+
+        [DllImport(DllName, EntryPoint = "disconnect_peer", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void DisconnectPeer(byte* peerUtf8, int peerLength, ulong reason);
+
+        [DllImport(DllName, EntryPoint = "ban_peer", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void BanPeer(byte* peerUtf8, int peerLength, ulong durationSecs);
+
+        [DllImport(DllName, EntryPoint = "unban_peer", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void UnbanPeer(byte* peerUtf8, int peerLength);
+
+        [DllImport(DllName, EntryPoint = "add_peer_filter_entry", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void AddPeerFilterEntry(byte allow, byte* entryUtf8, int entryLength);
+
+        [DllImport(DllName, EntryPoint = "remove_peer_filter_entry", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void RemovePeerFilterEntry(byte allow, byte* entryUtf8, int entryLength);
+
+        [DllImport(DllName, EntryPoint = "topic_peers", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe int TopicPeers(byte* topicUtf8, int topicLength, VisitPeer visitPeer);
+
+        // Subscribed peers that forwarded a message on the topic within the last minute; an
+        // approximation of the gossipsub mesh, which is not exposed.
+        [DllImport(DllName, EntryPoint = "active_topic_peers", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe int ActiveTopicPeers(byte* topicUtf8, int topicLength, VisitPeer visitPeer);
+
         [DllImport(DllName, EntryPoint = "register_handlers", CallingConvention = CallingConvention.Cdecl)]
         public static extern unsafe void RegisterHandlers(DiscoveredPeer discoveredPeer, ReceiveGossip receiveGossip, ReceiveRpc receiveRpc);
+
+        [DllImport(DllName, EntryPoint = "register_connection_gate", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void RegisterConnectionGate(AcceptConnection acceptConnection);
+
+        [DllImport(DllName, EntryPoint = "register_publish_result_handler", CallingConvention = CallingConvention.Cdecl)]
+        public static extern unsafe void RegisterPublishResultHandler(PublishResult publishResult);
         
         [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
         public unsafe delegate void DiscoveredPeer(byte* peerUtf8, int peerLength);
         
         [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
-        public unsafe delegate void ReceiveGossip(byte* messageIdUtf8, int messageIdLength, byte* peerIdUtf8, int peerIdLength, byte* topicUtf8, int topicLength, byte* data, int dataLength);
+        public unsafe delegate void ReceiveGossip(byte* messageIdUtf8, int messageIdLength, byte* peerIdUtf8, int peerIdLength, byte* authorUtf8, int authorLength, ulong sequenceNumber, byte** topicsUtf8, int* topicLengths, int topicsCount, byte* data, int dataLength);
 
         [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
         public unsafe delegate void ReceiveRpc(byte* methodUtf8, int methodLength, int requestResponseFlag, byte* peerUtf8, int peerLength, byte* data, int dataLength);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public unsafe delegate int AcceptConnection(byte* peerUtf8, int peerLength, byte* addressUtf8, int addressLength, int direction, byte* agentStringUtf8, int agentStringLength);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public delegate void PublishResult(ulong publishId, int result);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public unsafe delegate void VisitPeer(byte* peerUtf8, int peerLength);
     }
 }
\ No newline at end of file
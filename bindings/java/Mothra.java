@@ -7,7 +7,7 @@ import java.util.function.Function;
 public class Mothra {
     public static final String MOTHRA = "mothra-jni";
     public static Function<String, Boolean> DiscoveryMessage;
-    public static BiFunction<String, byte[], Boolean> ReceivedGossipMessage;
+    public static BiFunction<String[], byte[], Boolean> ReceivedGossipMessage;
     public static QuadFunction<String, Integer, String, byte[], Boolean> ReceivedRPCMessage;
     public static native void Init();
     public static native void Start(String[] client_constants, String[] args);
@@ -16,8 +16,12 @@ public class Mothra {
     public static void DiscoveredPeer(byte[] peer) {
         DiscoveryMessage.apply(new String(peer));
     }
-    public static void ReceiveGossip(byte[] message_id, byte[] peer_id, byte[] topic, byte[] message) {
-        ReceivedGossipMessage.apply(new String(topic), message);
+    public static void ReceiveGossip(byte[] message_id, byte[] peer_id, byte[] author, long sequence_number, byte[][] topics, byte[] message) {
+        String[] topicNames = new String[topics.length];
+        for (int i = 0; i < topics.length; i++) {
+            topicNames[i] = new String(topics[i]);
+        }
+        ReceivedGossipMessage.apply(topicNames, message);
     }
     public static void ReceiveRPC(byte[] method, int req_resp, byte[] peer, byte[] message) {
         ReceivedRPCMessage.apply(new String(method), req_resp, new String(peer), message);
@@ -112,33 +112,49 @@ void discovered_peer_callback(const unsigned char* peer, int peer_length) {
     }
 }
 
-void receive_gossip_callback(const unsigned char* message_id, int message_id_length, const unsigned char* peer_id, int peer_id_length, const unsigned char* topic, int topic_length, unsigned char* data, int data_length) {
+void receive_gossip_callback(const unsigned char* message_id, int message_id_length, const unsigned char* peer_id, int peer_id_length, const unsigned char* author, int author_length, unsigned long long sequence_number, const unsigned char** topics, const int* topic_lengths, int topics_count, unsigned char* data, int data_length) {
     JNIEnv *jenv;
     jint rs = (*jvm)->AttachCurrentThread(jvm, (void**)&jenv, NULL);
     assert (rs == JNI_OK);
     if(jenv != NULL) {
         jclass mothra_class;
+        jclass byte_array_class;
         jmethodID receivegossip_method;
-        jbyteArray jtopic;
+        jbyteArray jmessage_id;
+        jbyteArray jpeer_id;
+        jbyteArray jauthor;
+        jobjectArray jtopics;
         jbyteArray jdata;
         mothra_class = (*jenv)->FindClass(jenv, "p2p/mothra/Mothra");
-        if(!mothra_class){
+        byte_array_class = (*jenv)->FindClass(jenv, "[B");
+        if(!mothra_class || !byte_array_class){
             detach(jenv);
         }
-        //Put the native unsigned chars in the java byte array
-        jtopic = (*jenv)->NewByteArray(jenv, topic_length);
+        //Put the native unsigned chars in the java byte arrays
+        jmessage_id = (*jenv)->NewByteArray(jenv, message_id_length);
+        jpeer_id = (*jenv)->NewByteArray(jenv, peer_id_length);
+        jauthor = (*jenv)->NewByteArray(jenv, author_length);
+        jtopics = (*jenv)->NewObjectArray(jenv, topics_count, byte_array_class, NULL);
         jdata = (*jenv)->NewByteArray(jenv, data_length);
-        (*jenv)->SetByteArrayRegion(jenv, jdata, 0, data_length, (jbyte *)data);
-        (*jenv)->SetByteArrayRegion(jenv, jtopic, 0, topic_length, (jbyte *)topic);
-        if(!jdata || !jtopic){
+        if(!jmessage_id || !jpeer_id || !jauthor || !jtopics || !jdata){
             detach(jenv);
         }
-        receivegossip_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "ReceiveGossip", "([B[B)V");
+        (*jenv)->SetByteArrayRegion(jenv, jmessage_id, 0, message_id_length, (jbyte *)message_id);
+        (*jenv)->SetByteArrayRegion(jenv, jpeer_id, 0, peer_id_length, (jbyte *)peer_id);
+        (*jenv)->SetByteArrayRegion(jenv, jauthor, 0, author_length, (jbyte *)author);
+        for (int i = 0; i < topics_count; i++) {
+            jbyteArray jtopic = (*jenv)->NewByteArray(jenv, topic_lengths[i]);
+            (*jenv)->SetByteArrayRegion(jenv, jtopic, 0, topic_lengths[i], (jbyte *)topics[i]);
+            (*jenv)->SetObjectArrayElement(jenv, jtopics, i, jtopic);
+            (*jenv)->DeleteLocalRef(jenv, jtopic);
+        }
+        (*jenv)->SetByteArrayRegion(jenv, jdata, 0, data_length, (jbyte *)data);
+        receivegossip_method = (*jenv)->GetStaticMethodID(jenv, mothra_class, "ReceiveGossip", "([B[B[BJ[[B[B)V");
         if(!receivegossip_method){
-            printf("JNI Error: GetStaticMethodID was unable to find method: ReceiveGossip with signature: ([B[B)V\n");
+            printf("JNI Error: GetStaticMethodID was unable to find method: ReceiveGossip with signature: ([B[B[BJ[[B[B)V\n");
             detach(jenv);
         }
-        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, receivegossip_method, jtopic, jdata);
+        (*jenv)->CallStaticVoidMethod(jenv, mothra_class, receivegossip_method, jmessage_id, jpeer_id, jauthor, (jlong)sequence_number, jtopics, jdata);
     }
 }
 
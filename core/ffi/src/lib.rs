@@ -1,12 +1,14 @@
-use cast::i16;
+use cast::{i16, i32};
 use env_logger::Env;
 use mothra::{
-    cli_app, gossip, rpc_request, rpc_response, Mothra, NetworkGlobals, NetworkMessage, Subscriber,
-    TaskExecutor,
+    add_filter_entry, ban, cli_app, dial, disconnect, gossip, remove_filter_entry,
+    rpc_error_response, rpc_request, rpc_response, unban, validate_gossip, GossipTopic, Mothra,
+    MothraPeerId, NetworkGlobals, NetworkMessage, PublishError, Subscriber, TaskExecutor,
 };
 use slog::{debug, info, o, trace, warn, Drain, Level, Logger};
 use std::ffi::CStr;
-use std::os::raw::{c_char, c_uchar};
+use std::os::raw::{c_char, c_int, c_uchar};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::{process, slice, str};
 use tokio::runtime::Runtime;
@@ -28,8 +30,12 @@ type ReceiveGossipType = unsafe extern "C" fn(
     message_id_length: i16,
     peer_id: *const c_uchar,
     peer_id_length: i16,
-    topic: *const c_uchar,
-    topic_length: i16,
+    author: *const c_uchar,
+    author_length: i16,
+    sequence_number: u64,
+    topics: *const *const c_uchar,
+    topic_lengths: *const c_int,
+    topics_count: i16,
     data: *mut c_uchar,
     data_length: i16,
 );
@@ -42,9 +48,24 @@ type ReceiveRpcType = unsafe extern "C" fn(
     data: *mut c_uchar,
     data_length: i16,
 );
+type AcceptConnectionType = unsafe extern "C" fn(
+    peer: *const c_uchar,
+    peer_length: i16,
+    address: *const c_uchar,
+    address_length: i16,
+    direction: i16,
+    agent_string: *const c_uchar,
+    agent_string_length: i16,
+) -> c_int;
 static mut DISCOVERED_PEER_PTR: Option<DiscoveredPeerType> = None;
+type PublishResultType = unsafe extern "C" fn(publish_id: u64, result: c_int);
+type PeerVisitorType = unsafe extern "C" fn(peer: *const c_uchar, peer_length: i16);
 static mut RECEIVE_GOSSIP_PTR: Option<ReceiveGossipType> = None;
 static mut RECEIVE_RPC_PTR: Option<ReceiveRpcType> = None;
+static mut ACCEPT_CONNECTION_PTR: Option<AcceptConnectionType> = None;
+static mut PUBLISH_RESULT_PTR: Option<PublishResultType> = None;
+/// The id returned by the next `send_gossip` call.
+static NEXT_PUBLISH_ID: AtomicU64 = AtomicU64::new(0);
 
 struct Client;
 
@@ -68,12 +89,21 @@ impl Subscriber for Client {
         sequence_number: u64,
         agent_string: String,
         peer_id: String,
-        topic: String,
+        author: Option<String>,
+        topics: Vec<String>,
         mut data: Vec<u8>,
     ) {
-        let message_id_length = i16(topic.len()).unwrap();
-        let peer_id_length = i16(topic.len()).unwrap();
-        let topic_length = i16(topic.len()).unwrap();
+        let message_id_length = i16(message_id.len()).unwrap();
+        let peer_id_length = i16(peer_id.len()).unwrap();
+        // the author of unsigned messages is empty
+        let author = author.unwrap_or_default();
+        let author_length = i16(author.len()).unwrap();
+        let topic_ptrs: Vec<*const c_uchar> = topics.iter().map(|topic| topic.as_ptr()).collect();
+        let topic_lengths: Vec<c_int> = topics
+            .iter()
+            .map(|topic| i32(topic.len()).unwrap())
+            .collect();
+        let topics_count = i16(topics.len()).unwrap();
         let data_length = i16(data.len()).unwrap();
         unsafe {
             RECEIVE_GOSSIP_PTR.unwrap()(
@@ -81,8 +111,12 @@ impl Subscriber for Client {
                 message_id_length,
                 peer_id.as_ptr(),
                 peer_id_length,
-                topic.as_ptr(),
-                topic_length,
+                author.as_ptr(),
+                author_length,
+                sequence_number,
+                topic_ptrs.as_ptr(),
+                topic_lengths.as_ptr(),
+                topics_count,
                 data.as_mut_ptr(),
                 data_length,
             )
@@ -105,6 +139,35 @@ impl Subscriber for Client {
             )
         };
     }
+
+    fn accept_connection(
+        &self,
+        peer_id: String,
+        address: String,
+        direction: u8,
+        agent_string: String,
+    ) -> bool {
+        match unsafe { ACCEPT_CONNECTION_PTR } {
+            Some(accept_connection) => {
+                let peer_length = i16(peer_id.len()).unwrap();
+                let address_length = i16(address.len()).unwrap();
+                let agent_string_length = i16(agent_string.len()).unwrap();
+                unsafe {
+                    accept_connection(
+                        peer_id.as_ptr(),
+                        peer_length,
+                        address.as_ptr(),
+                        address_length,
+                        i16(direction),
+                        agent_string.as_ptr(),
+                        agent_string_length,
+                    ) != 0
+                }
+            }
+            // accept every connection if no gate has been registered
+            None => true,
+        }
+    }
 }
 
 #[no_mangle]
@@ -118,6 +181,22 @@ pub unsafe extern "C" fn register_handlers(
     RECEIVE_RPC_PTR = Some(receive_rpc);
 }
 
+/// Registers a callback deciding whether a connection is accepted. It must return a non-zero
+/// value to accept the connection, and is called from the network threads once per established
+/// connection, including the ones to discovered peers.
+#[no_mangle]
+pub unsafe extern "C" fn register_connection_gate(accept_connection: AcceptConnectionType) {
+    ACCEPT_CONNECTION_PTR = Some(accept_connection);
+}
+
+/// Registers a callback receiving the result of each `send_gossip` call, identified by the id it
+/// returned. The result is an upper bound of the number of peers the message was sent to, or a
+/// negative error code (see `publish_result_code`). It is called from the network threads.
+#[no_mangle]
+pub unsafe extern "C" fn register_publish_result_handler(publish_result: PublishResultType) {
+    PUBLISH_RESULT_PTR = Some(publish_result);
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn network_start(
     client_constants: *mut *mut c_char,
@@ -175,6 +254,14 @@ pub unsafe extern "C" fn network_start(
             process::exit(1);
         });
     let config = Mothra::get_config(client_name, client_version, protocol_version, &matches);
+    // the host application asked for the configuration rather than a running node
+    if let Some(dump) = config.requested_dump(&matches) {
+        match dump {
+            Ok(dump) => println!("{}", dump),
+            Err(e) => eprintln!("{}", e),
+        }
+        return;
+    }
     // configure logging
     env_logger::Builder::from_env(Env::default()).init();
     let decorator = slog_term::TermDecorator::new().build();
@@ -195,7 +282,7 @@ pub unsafe extern "C" fn network_start(
     let enr_fork_id = [0u8; 32].to_vec();
     let meta_data = [0u8; 32].to_vec();
     let ping_data = [0u8; 32].to_vec();
-    let client = Box::new(Client::new()) as Box<dyn Subscriber + Send>;
+    let client = Box::new(Client::new()) as Box<dyn Subscriber + Send + Sync>;
     let mut runtime = Runtime::new()
         .map_err(|e| format!("Failed to start runtime: {:?}", e))
         .unwrap();
@@ -233,13 +320,90 @@ pub unsafe extern "C" fn send_gossip(
     topic_length: usize,
     data: *mut c_uchar,
     data_length: usize,
-) {
-    gossip(
+) -> u64 {
+    let publish_id = NEXT_PUBLISH_ID.fetch_add(1, Ordering::Relaxed);
+    let result = gossip(
         CONTEXT[0].network_send.clone(),
         str::from_utf8_unchecked(slice::from_raw_parts(topic, topic_length)).into(),
         slice::from_raw_parts_mut(data, data_length).to_vec(),
         CONTEXT[0].log.clone(),
     );
+    if let Some(publish_result) = PUBLISH_RESULT_PTR {
+        CONTEXT[0].runtime.spawn(async move {
+            let code = publish_result_code(result.await.ok());
+            unsafe { publish_result(publish_id, code) };
+        });
+    }
+    publish_id
+}
+
+/// Calls `visit_peer` with each connected peer subscribed to a topic, and returns their number.
+#[no_mangle]
+pub unsafe extern "C" fn topic_peers(
+    topic: *mut c_uchar,
+    topic_length: usize,
+    visit_peer: PeerVisitorType,
+) -> c_int {
+    let topic = str::from_utf8_unchecked(slice::from_raw_parts(topic, topic_length));
+    let peers = CONTEXT[0]
+        .network_globals
+        .topic_peers(&GossipTopic::new(topic.into()));
+    visit_peers(peers, visit_peer)
+}
+
+/// Calls `visit_peer` with each connected peer subscribed to a topic that forwarded us a message
+/// on it within the last minute, and returns their number. This approximates our mesh for the
+/// topic, which gossipsub does not expose.
+#[no_mangle]
+pub unsafe extern "C" fn active_topic_peers(
+    topic: *mut c_uchar,
+    topic_length: usize,
+    visit_peer: PeerVisitorType,
+) -> c_int {
+    let topic = str::from_utf8_unchecked(slice::from_raw_parts(topic, topic_length));
+    let peers = CONTEXT[0]
+        .network_globals
+        .active_topic_peers(&GossipTopic::new(topic.into()));
+    visit_peers(peers, visit_peer)
+}
+
+unsafe fn visit_peers(peers: Vec<MothraPeerId>, visit_peer: PeerVisitorType) -> c_int {
+    for peer in &peers {
+        let peer = peer.to_string();
+        visit_peer(peer.as_ptr(), i16(peer.len()).unwrap());
+    }
+    i32(peers.len()).unwrap_or(c_int::max_value())
+}
+
+/// The estimated number of peers a message was sent to, or a negative error code: -1 if no peer is
+/// subscribed to the topic, -2 for a duplicate message, -3 for a message that is too large, -4 if
+/// the message could not be encoded and -5 if the network service stopped.
+fn publish_result_code(result: Option<Result<usize, PublishError>>) -> c_int {
+    match result {
+        Some(Ok(max_peers)) => i32(max_peers).unwrap_or(c_int::max_value()),
+        Some(Err(PublishError::InsufficientPeers)) => -1,
+        Some(Err(PublishError::Duplicate)) => -2,
+        Some(Err(PublishError::TooLarge { .. })) => -3,
+        Some(Err(PublishError::Encoding(_))) => -4,
+        None => -5,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn validate_gossip_message(
+    message_id: *mut c_uchar,
+    message_id_length: usize,
+    peer: *mut c_uchar,
+    peer_length: usize,
+    valid: c_uchar,
+) {
+    validate_gossip(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(message_id, message_id_length)).into(),
+        str::from_utf8_unchecked(slice::from_raw_parts(peer, peer_length)).into(),
+        valid != 0,
+        CONTEXT[0].log.clone(),
+    );
 }
 
 #[no_mangle]
@@ -277,3 +441,92 @@ pub unsafe extern "C" fn send_rpc_response(
         CONTEXT[0].log.clone(),
     );
 }
+
+/// Rejects the request `receive_rpc` delivered with `request_index`.
+#[no_mangle]
+pub unsafe extern "C" fn send_rpc_error_response(
+    method: *mut c_uchar,
+    method_length: usize,
+    peer: *mut c_uchar,
+    peer_length: usize,
+    request_index: c_uchar,
+    error_code: c_uchar,
+    reason: *mut c_uchar,
+    reason_length: usize,
+) {
+    rpc_error_response(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(method, method_length)).into(),
+        str::from_utf8_unchecked(slice::from_raw_parts(peer, peer_length)).into(),
+        request_index,
+        error_code,
+        str::from_utf8_unchecked(slice::from_raw_parts(reason, reason_length)).into(),
+        CONTEXT[0].log.clone(),
+    );
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dial_peer(address: *mut c_uchar, address_length: usize) {
+    dial(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(address, address_length)).into(),
+        CONTEXT[0].log.clone(),
+    );
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn disconnect_peer(peer: *mut c_uchar, peer_length: usize, reason: u64) {
+    disconnect(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(peer, peer_length)).into(),
+        reason,
+        CONTEXT[0].log.clone(),
+    );
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ban_peer(peer: *mut c_uchar, peer_length: usize, duration_secs: u64) {
+    ban(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(peer, peer_length)).into(),
+        duration_secs,
+        CONTEXT[0].log.clone(),
+    );
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn unban_peer(peer: *mut c_uchar, peer_length: usize) {
+    unban(
+        CONTEXT[0].network_send.clone(),
+        str::from_utf8_unchecked(slice::from_raw_parts(peer, peer_length)).into(),
+        CONTEXT[0].log.clone(),
+    );
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn add_peer_filter_entry(
+    allow: c_uchar,
+    entry: *mut c_uchar,
+    entry_length: usize,
+) {
+    add_filter_entry(
+        CONTEXT[0].network_send.clone(),
+        allow != 0,
+        str::from_utf8_unchecked(slice::from_raw_parts(entry, entry_length)).into(),
+        CONTEXT[0].log.clone(),
+    );
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn remove_peer_filter_entry(
+    allow: c_uchar,
+    entry: *mut c_uchar,
+    entry_length: usize,
+) {
+    remove_filter_entry(
+        CONTEXT[0].network_send.clone(),
+        allow != 0,
+        str::from_utf8_unchecked(slice::from_raw_parts(entry, entry_length)).into(),
+        CONTEXT[0].log.clone(),
+    );
+}
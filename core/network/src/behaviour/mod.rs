@@ -1,6 +1,12 @@
-use crate::peer_manager::{PeerManager, PeerManagerEvent};
+use crate::config::SigningPolicy;
+use crate::metrics;
+use crate::peer_manager::{PeerAction, PeerManager, PeerManagerEvent};
 use crate::rpc::*;
-use crate::types::{EnrForkId, GossipKind, GossipTopic, SubnetId};
+use crate::types::encryption::TopicCipher;
+use crate::types::signing::{self, Authorship};
+use crate::types::{
+    envelope, EnrForkId, Eth2Kind, GossipEncoding, GossipKind, GossipTopic, SubnetId, TopicPattern,
+};
 
 use crate::{error, Enr, NetworkConfig, NetworkGlobals, TopicHash};
 use futures::prelude::*;
@@ -20,21 +26,40 @@ use libp2p::{
     PeerId,
 };
 use lru::LruCache;
+use rate_limiter::TopicRateLimiter;
+use sha2::{Digest, Sha256};
 use slog::{crit, debug, o};
 use std::{
+    collections::{HashMap, HashSet},
+    fmt,
     marker::PhantomData,
     sync::Arc,
     task::{Context, Poll},
-    time::Instant,
+    time::{Duration, Instant, SystemTime},
 };
 
 mod handler;
+mod rate_limiter;
 
 const MAX_IDENTIFY_ADDRESSES: usize = 10;
+/// The number of received gossipsub messages whose topics are remembered until they are
+/// propagated.
+const RECEIVED_TOPICS_CACHE_SIZE: usize = 1024;
+/// The number of published messages remembered to detect duplicates.
+const PUBLISHED_MESSAGES_CACHE_SIZE: usize = 1024;
 
 /// Builds the network behaviour that manages the core protocols of eth2.
 /// This core behaviour is managed by `Behaviour` which adds peer management to all core
 /// behaviours.
+///
+/// The gossipsub implementation in use predates gossipsub v1.1, and several features are built
+/// around it here:
+/// - it does not expose its mesh nor the peers a message is sent to,
+/// - it does not score peers, which the `PeerManager` does from the events reported here,
+/// - it does not sign messages, it only carries the peer id of their author when source ids are
+///   enabled,
+/// - its message id function is a plain `fn` pointer, which cannot capture any state,
+/// - its duplicate cache only holds the ids of received messages.
 pub struct Behaviour {
     /// The routing pub-sub mechanism.
     gossipsub: Gossipsub,
@@ -60,6 +85,38 @@ pub struct Behaviour {
     // NOTE: This can be accessed via the network_globals ENR. However we keep it here for quick
     // lookups for every gossipsub message send.
     enr_fork_id: EnrForkId,
+    /// The topics of recently received gossipsub messages, to label the propagation metrics.
+    received_topics: LruCache<MessageId, Vec<TopicHash>>,
+    /// The maximum size of a decoded gossipsub message.
+    max_gossip_size: usize,
+    /// The target size of a gossipsub mesh, which caps the estimated recipients of a message.
+    mesh_n: usize,
+    /// When recently published messages were published, by hash of their topic and data.
+    ///
+    /// Gossipsub's own duplicate cache holds the ids of received messages and never reports a
+    /// duplicate to the publisher, so this cache is what lets `publish` return
+    /// `PublishError::Duplicate`, whatever the message id function.
+    published_messages: LruCache<Vec<u8>, Instant>,
+    /// How long publishing the same message again is reported as a duplicate.
+    duplicate_cache_time: Duration,
+    /// The time to live of the messages of the topics whose messages carry a timestamp envelope.
+    message_ttls: HashMap<TopicHash, Duration>,
+    /// The subscribed topic patterns, with the topics each of them subscribed to.
+    topic_patterns: HashMap<TopicPattern, HashSet<TopicHash>>,
+    /// The maximum number of topics a topic pattern subscribes to.
+    max_pattern_topics: usize,
+    /// The maximum size of the decoded messages of the topics with a size limit.
+    max_message_sizes: HashMap<TopicHash, usize>,
+    /// Limits the messages each peer forwards on the topics with a rate limit.
+    rate_limiter: TopicRateLimiter,
+    /// Whether published messages are signed and received messages must be.
+    signing_policy: SigningPolicy,
+    /// The key published messages are signed with.
+    local_key: Keypair,
+    /// Encrypts and decrypts the messages of the private topics.
+    topic_ciphers: HashMap<TopicHash, TopicCipher>,
+    /// Whether gossipsub messages are also delivered as sent and received, to capture them.
+    capture_traffic: bool,
     /// Logger for behaviour actions.
     log: slog::Logger,
 }
@@ -94,6 +151,8 @@ impl NetworkBehaviour for Behaviour {
     }
 
     fn inject_disconnected(&mut self, peer_id: &PeerId) {
+        self.network_globals.remove_peer_topics(peer_id);
+        self.rate_limiter.remove_peer(peer_id);
         delegate_to_behaviours!(self, inject_disconnected, peer_id);
     }
 
@@ -254,6 +313,36 @@ impl Behaviour {
 
         let ping_data = network_globals.ping_data.read().clone();
 
+        let message_ttls = config
+            .message_ttls
+            .iter()
+            .map(|(topic, ttl_ms)| {
+                (
+                    GossipTopic::new(topic.clone()).topic_hash(),
+                    Duration::from_millis(*ttl_ms),
+                )
+            })
+            .collect();
+
+        let mut max_message_sizes = HashMap::new();
+        let mut rates = HashMap::new();
+        for (topic, limits) in &config.gossipsub.topic_limits {
+            let topic_hash = GossipTopic::new(topic.clone()).topic_hash();
+            if limits.max_message_size > 0 {
+                max_message_sizes.insert(topic_hash.clone(), limits.max_message_size);
+            }
+            if limits.max_messages_per_second > 0 {
+                rates.insert(topic_hash, limits.max_messages_per_second);
+            }
+        }
+
+        let mut topic_ciphers = HashMap::new();
+        for (topic, keys) in &config.topic_keys {
+            let cipher = TopicCipher::new(keys)
+                .map_err(|e| format!("Invalid keys of topic {}: {}", topic, e))?;
+            topic_ciphers.insert(GossipTopic::new(topic.clone()).topic_hash(), cipher);
+        }
+
         Ok(Behaviour {
             mothra_rpc: RPC::new(log.clone()),
             gossipsub: Gossipsub::new(local_peer_id, config.gs_config.clone()),
@@ -265,6 +354,20 @@ impl Behaviour {
             ping_data,
             network_globals,
             enr_fork_id,
+            received_topics: LruCache::new(RECEIVED_TOPICS_CACHE_SIZE),
+            max_gossip_size: config.gossipsub.max_transmit_size,
+            mesh_n: config.gossipsub.mesh_n,
+            published_messages: LruCache::new(PUBLISHED_MESSAGES_CACHE_SIZE),
+            duplicate_cache_time: Duration::from_millis(config.gossipsub.duplicate_cache_time_ms),
+            message_ttls,
+            topic_patterns: HashMap::new(),
+            max_pattern_topics: config.max_pattern_topics,
+            max_message_sizes,
+            rate_limiter: TopicRateLimiter::new(rates),
+            signing_policy: config.gossipsub.signing_policy,
+            local_key: local_key.clone(),
+            topic_ciphers,
+            capture_traffic: config.capture_traffic,
             log: behaviour_log,
         })
     }
@@ -293,8 +396,28 @@ impl Behaviour {
         self.unsubscribe(gossip_topic)
     }
 
+    /// Subscribes to the eth2 topic of a kind for the current fork.
+    pub fn subscribe_eth2(&mut self, kind: Eth2Kind) -> bool {
+        let gossip_topic = GossipTopic::eth2(self.fork_digest(), kind, GossipEncoding::SSZSnappy);
+        self.subscribe(gossip_topic)
+    }
+
+    /// Unsubscribes from the eth2 topic of a kind for the current fork.
+    pub fn unsubscribe_eth2(&mut self, kind: Eth2Kind) -> bool {
+        let gossip_topic = GossipTopic::eth2(self.fork_digest(), kind, GossipEncoding::SSZSnappy);
+        self.unsubscribe(gossip_topic)
+    }
+
+    /// The fork digest of eth2 topics, the first four bytes of the ENR fork id.
+    fn fork_digest(&self) -> [u8; 4] {
+        let mut fork_digest = [0u8; 4];
+        let len = self.enr_fork_id.len().min(4);
+        fork_digest[..len].copy_from_slice(&self.enr_fork_id[..len]);
+        fork_digest
+    }
+
     /// Subscribes to a gossipsub topic.
-    fn subscribe(&mut self, topic: GossipTopic) -> bool {
+    pub fn subscribe(&mut self, topic: GossipTopic) -> bool {
         // update the network globals
         self.network_globals
             .gossipsub_subscriptions
@@ -307,7 +430,7 @@ impl Behaviour {
     }
 
     /// Unsubscribe from a gossipsub topic.
-    fn unsubscribe(&mut self, topic: GossipTopic) -> bool {
+    pub fn unsubscribe(&mut self, topic: GossipTopic) -> bool {
         // update the network globals
         self.network_globals
             .gossipsub_subscriptions
@@ -317,18 +440,192 @@ impl Behaviour {
         self.gossipsub.unsubscribe(topic.into())
     }
 
-    /// Publishes a list of messages on the pubsub (gossipsub) behaviour, choosing the encoding.
-    pub fn publish(&mut self, topic: GossipTopic, message: Vec<u8>) {
+    /// Subscribes to the topics matching a pattern, up to `max_pattern_topics` of them: those that
+    /// peers are already subscribed to and those they subscribe to later. Topics that are already
+    /// subscribed to are not counted. Returns false if the pattern is already subscribed to.
+    pub fn subscribe_pattern(&mut self, pattern: TopicPattern) -> bool {
+        if self.topic_patterns.contains_key(&pattern) {
+            return false;
+        }
+        debug!(self.log, "Subscribed to topic pattern"; "pattern" => pattern.to_string());
+        self.topic_patterns.insert(pattern, HashSet::new());
+        self.expand_patterns_to_peer_topics();
+        true
+    }
+
+    /// Unsubscribes from a pattern and from the topics it subscribed to, unless another pattern
+    /// matches them.
+    pub fn unsubscribe_pattern(&mut self, pattern: &TopicPattern) -> bool {
+        let topics = match self.topic_patterns.remove(pattern) {
+            Some(topics) => topics,
+            None => return false,
+        };
+        for topic in topics {
+            self.unsubscribe(GossipTopic::new(topic.into_string()));
+        }
+        self.expand_patterns_to_peer_topics();
+        true
+    }
+
+    /// Subscribes the patterns to the matching topics peers are subscribed to.
+    fn expand_patterns_to_peer_topics(&mut self) {
+        let peer_topics = self
+            .network_globals
+            .topic_peers
+            .read()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        for topic in peer_topics {
+            self.expand_patterns(&topic);
+        }
+    }
+
+    /// Subscribes to a topic a peer subscribed to if it matches a pattern that has not reached
+    /// its maximum number of topics.
+    fn expand_patterns(&mut self, topic: &TopicHash) {
+        if self.topic_patterns.is_empty()
+            || self
+                .network_globals
+                .gossipsub_subscriptions
+                .read()
+                .contains(&GossipTopic::new(topic.as_str().into()))
+        {
+            return;
+        }
+        let matching = self
+            .topic_patterns
+            .iter()
+            .filter(|(pattern, _)| pattern.matches(topic.as_str()))
+            .collect::<Vec<_>>();
+        let pattern = match matching
+            .iter()
+            .find(|(_, topics)| topics.len() < self.max_pattern_topics)
+        {
+            Some((pattern, _)) => TopicPattern::clone(pattern),
+            None => {
+                if let Some((pattern, _)) = matching.first() {
+                    debug!(self.log, "Topic pattern reached its maximum number of topics";
+                        "topic" => topic.as_str(), "pattern" => pattern.to_string(),
+                        "max_pattern_topics" => self.max_pattern_topics);
+                }
+                return;
+            }
+        };
+
+        let gossip_topic = GossipTopic::new(topic.as_str().into());
+        if let Err(e) = gossip_topic.validate() {
+            debug!(self.log, "Not subscribing to an invalid topic matching a pattern";
+                "topic" => topic.as_str(), "pattern" => pattern.to_string(), "error" => e);
+            return;
+        }
+        if self.subscribe(gossip_topic) {
+            debug!(self.log, "Subscribed to a topic matching a pattern";
+                "topic" => topic.as_str(), "pattern" => pattern.to_string());
+            if let Some(topics) = self.topic_patterns.get_mut(&pattern) {
+                topics.insert(topic.clone());
+            }
+        }
+    }
+
+    /// Publishes a message on the pubsub (gossipsub) behaviour, choosing the encoding.
+    ///
+    /// Returns an upper bound of the number of peers the message is sent to: the connected peers
+    /// subscribed to the topic, capped at the mesh size. Gossipsub does not report the actual
+    /// recipients (see `Behaviour`), and a mesh that is still being formed may have fewer.
+    ///
+    /// The message is wrapped in layers, innermost first: a timestamp envelope on topics with a
+    /// time to live, encryption on private topics, a signature envelope unless messages are
+    /// anonymous, and the encoding of the topic. Received messages are unwrapped in the reverse
+    /// order: decoded, verified, decrypted and checked against their time to live.
+    pub fn publish(&mut self, topic: GossipTopic, message: Vec<u8>) -> Result<usize, PublishError> {
+        let topic_str: String = topic.clone().into();
+        let topic_hash = topic.topic_hash();
+        // the envelopes differ each time a message is published, so duplicates are identified by
+        // the message itself
+        let message_hash = published_message_hash(&topic_hash, &message);
+        let message = if self.message_ttls.contains_key(&topic_hash) {
+            envelope::seal(message, SystemTime::now())
+        } else {
+            message
+        };
+        let message = match self.topic_ciphers.get(&topic_hash) {
+            Some(cipher) => cipher
+                .encrypt(&topic_hash, &message)
+                .map_err(PublishError::Encoding)?,
+            None => message,
+        };
+        let message = signing::seal(
+            self.signing_policy,
+            &self.local_key,
+            &[topic_hash.clone()],
+            message,
+        )
+        .map_err(PublishError::Encoding)?;
+        let message = topic
+            .encoding()
+            .encode(message)
+            .map_err(PublishError::Encoding)?;
+        if message.len() > self.max_gossip_size {
+            return Err(PublishError::TooLarge {
+                size: message.len(),
+                max_size: self.max_gossip_size,
+            });
+        }
+
+        if let Some(published) = self.published_messages.get(&message_hash) {
+            if published.elapsed() < self.duplicate_cache_time {
+                return Err(PublishError::Duplicate);
+            }
+        }
+
+        // gossipsub sends the message to its mesh peers, or to fanout peers if we are not
+        // subscribed to the topic, both of which are chosen among the peers subscribed to it, so
+        // these bound the recipients
+        let topic_peers = self.network_globals.topic_peers(&topic).len();
+        if topic_peers == 0 {
+            return Err(PublishError::InsufficientPeers);
+        }
+
+        self.published_messages.put(message_hash, Instant::now());
+        metrics::inc_counter_vec(&metrics::GOSSIP_MESSAGES_PUBLISHED, &[&topic_str]);
+        if self.capture_traffic {
+            self.events.push(BehaviourEvent::PubsubPublished {
+                topic: topic.clone(),
+                data: message.clone(),
+            });
+        }
         self.gossipsub.publish(&topic.into(), message);
+        Ok(topic_peers.min(self.mesh_n))
     }
 
     /// Forwards a message that is waiting in gossipsub's mcache. Messages are only propagated
     /// once validated by the beacon chain.
+    ///
+    /// Messages from peers whose score is below the publish threshold are not propagated.
     pub fn propagate_message(&mut self, propagation_source: &PeerId, message_id: MessageId) {
+        let topics = self.received_topics.pop(&message_id);
+        if !self.peer_manager.can_propagate_from(propagation_source) {
+            debug!(self.log, "Not propagating message from a peer below the publish threshold";
+                "peer_id" => propagation_source.to_string(), "message_id" => message_id.to_string());
+            return;
+        }
+        for topic in topics.unwrap_or_default() {
+            metrics::inc_counter_vec(&metrics::GOSSIP_MESSAGES_PROPAGATED, &[topic.as_str()]);
+        }
         self.gossipsub
             .propagate_message(&message_id, propagation_source);
     }
 
+    /// Informs the peer manager that a received message was rejected by the application, which
+    /// counts as an invalid delivery in the score of the peer that sent it.
+    pub fn reject_message(&mut self, propagation_source: &PeerId, message_id: MessageId) {
+        if let Some(topics) = self.received_topics.pop(&message_id) {
+            self.peer_manager
+                .invalid_gossip_message(propagation_source, &topics);
+        }
+    }
+
     /// Send a request to a peer over RPC.
     pub fn send_request(&mut self, peer_id: PeerId, request_id: RequestId, request: Request) {
         self.mothra_rpc
@@ -346,7 +643,7 @@ impl Behaviour {
     }
 
     /// Inform the peer that their request produced an error.
-    pub fn _send_error_reponse(
+    pub fn send_error_response(
         &mut self,
         peer_id: PeerId,
         id: PeerRequestId,
@@ -362,13 +659,17 @@ impl Behaviour {
 
     /* Peer management functions */
 
-    /// Notify discovery that the peer has been banned.
+    /// Notify the peer manager that the peer has been banned.
     // TODO: Remove this and integrate all disconnection/banning logic inside the peer manager.
-    pub fn peer_banned(&mut self, _peer_id: PeerId) {}
+    pub fn peer_banned(&mut self, peer_id: PeerId) {
+        self.peer_manager.notify_banned(&peer_id);
+    }
 
-    /// Notify discovery that the peer has been unbanned.
+    /// Notify the peer manager that the peer has been unbanned.
     // TODO: Remove this and integrate all disconnection/banning logic inside the peer manager.
-    pub fn peer_unbanned(&mut self, _peer_id: &PeerId) {}
+    pub fn peer_unbanned(&mut self, peer_id: &PeerId) {
+        self.peer_manager.notify_unbanned(peer_id);
+    }
 
     /// Returns an iterator over all enr entries in the DHT.
     pub fn enr_entries(&mut self) -> Vec<Enr> {
@@ -435,6 +736,162 @@ impl Behaviour {
     fn on_gossip_event(&mut self, event: GossipsubEvent) {
         match event {
             GossipsubEvent::Message(propagation_source, id, gs_msg) => {
+                for topic in &gs_msg.topics {
+                    metrics::inc_counter_vec(&metrics::GOSSIP_MESSAGES_RECEIVED, &[topic.as_str()]);
+                }
+                if !self
+                    .peer_manager
+                    .gossip_message(&propagation_source, &gs_msg.topics)
+                {
+                    debug!(self.log, "Ignoring message from a graylisted peer";
+                        "peer_id" => propagation_source.to_string());
+                    return;
+                }
+                // the limits of every topic of the message apply
+                if let Err(topic) = self.rate_limiter.allow(&gs_msg.topics, &propagation_source) {
+                    debug!(self.log, "Dropping gossipsub message over the rate limit of its topic";
+                        "peer_id" => propagation_source.to_string(), "topic" => topic.as_str());
+                    metrics::inc_counter_vec(
+                        &metrics::GOSSIP_LIMIT_VIOLATIONS,
+                        &[topic.as_str(), "rate"],
+                    );
+                    self.peer_manager
+                        .report_peer(&propagation_source, PeerAction::HighToleranceError);
+                    return;
+                }
+                // messages are decoded according to their first topic, like they are delivered
+                let encoding = gs_msg.topics.first().map_or(GossipEncoding::Raw, |topic| {
+                    GossipEncoding::from_topic(topic.as_str())
+                });
+                let raw = if self.capture_traffic {
+                    Some(gs_msg.data.clone())
+                } else {
+                    None
+                };
+                let message = match encoding.decode(gs_msg.data, self.max_gossip_size) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        debug!(self.log, "Could not decode gossipsub message";
+                            "peer_id" => propagation_source.to_string(), "error" => e);
+                        self.peer_manager
+                            .invalid_gossip_message(&propagation_source, &gs_msg.topics);
+                        return;
+                    }
+                };
+                let authorship =
+                    signing::open(self.signing_policy, message, &gs_msg.topics, &gs_msg.source);
+                let (author, message) = match authorship {
+                    Ok(Authorship::Signed { author, message }) => (Some(author), message),
+                    Ok(Authorship::Unsigned(message))
+                        if self.signing_policy != SigningPolicy::Strict =>
+                    {
+                        (None, message)
+                    }
+                    Ok(Authorship::Unsigned(_)) => {
+                        debug!(self.log, "Dropping unsigned gossipsub message";
+                            "peer_id" => propagation_source.to_string());
+                        self.peer_manager
+                            .invalid_gossip_message(&propagation_source, &gs_msg.topics);
+                        return;
+                    }
+                    Err(e) => {
+                        debug!(self.log, "Dropping gossipsub message with an invalid signature";
+                            "peer_id" => propagation_source.to_string(),
+                            "author" => gs_msg.source.to_string(), "error" => e);
+                        self.peer_manager
+                            .invalid_gossip_message(&propagation_source, &gs_msg.topics);
+                        return;
+                    }
+                };
+                // private topics are decrypted with the keys of the first topic. Messages that
+                // cannot be decrypted are ignored and not propagated, while peers without keys for
+                // the topic deliver and propagate the ciphertext
+                let cipher = gs_msg
+                    .topics
+                    .first()
+                    .and_then(|topic| self.topic_ciphers.get(topic).map(|cipher| (topic, cipher)));
+                let message = match cipher {
+                    Some((topic, cipher)) => match cipher.decrypt(topic, &message) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            debug!(self.log, "Ignoring gossipsub message that cannot be decrypted";
+                                "peer_id" => propagation_source.to_string(),
+                                "topic" => topic.as_str(), "error" => e);
+                            metrics::inc_counter_vec(
+                                &metrics::GOSSIP_UNDECRYPTABLE_MESSAGES,
+                                &[topic.as_str()],
+                            );
+                            return;
+                        }
+                    },
+                    None => message,
+                };
+                // like the encoding, the time to live is the one of the first topic
+                let ttl = gs_msg
+                    .topics
+                    .first()
+                    .and_then(|topic| self.message_ttls.get(topic).map(|ttl| (topic, *ttl)));
+                let message = match ttl {
+                    Some((ttl_topic, ttl)) => match envelope::open(message) {
+                        Ok((published, message)) => {
+                            match envelope::age(published, SystemTime::now()) {
+                                Ok(age) if age > ttl => {
+                                    // the application never sees the message, so it is not
+                                    // propagated
+                                    debug!(self.log, "Dropping stale gossipsub message";
+                                        "peer_id" => propagation_source.to_string(),
+                                        "age" => format!("{:?}", age));
+                                    // only the topics with a time to live are known to be bounded
+                                    metrics::inc_counter_vec(
+                                        &metrics::GOSSIP_STALE_MESSAGES,
+                                        &[ttl_topic.as_str()],
+                                    );
+                                    self.peer_manager
+                                        .stale_gossip_message(&propagation_source, &gs_msg.topics);
+                                    return;
+                                }
+                                Ok(_) => message,
+                                Err(e) => {
+                                    debug!(self.log, "Dropping gossipsub message from the future";
+                                        "peer_id" => propagation_source.to_string(), "error" => e);
+                                    self.peer_manager.invalid_gossip_message(
+                                        &propagation_source,
+                                        &gs_msg.topics,
+                                    );
+                                    return;
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            debug!(self.log, "Could not open gossipsub message envelope";
+                                "peer_id" => propagation_source.to_string(), "error" => e);
+                            self.peer_manager
+                                .invalid_gossip_message(&propagation_source, &gs_msg.topics);
+                            return;
+                        }
+                    },
+                    None => message,
+                };
+                let oversized = gs_msg.topics.iter().find(|topic| {
+                    self.max_message_sizes
+                        .get(topic)
+                        .map_or(false, |max_size| message.len() > *max_size)
+                });
+                if let Some(topic) = oversized {
+                    debug!(self.log, "Dropping gossipsub message over the size limit of its topic";
+                        "peer_id" => propagation_source.to_string(), "topic" => topic.as_str(),
+                        "size" => message.len());
+                    metrics::inc_counter_vec(
+                        &metrics::GOSSIP_LIMIT_VIOLATIONS,
+                        &[topic.as_str(), "size"],
+                    );
+                    self.peer_manager
+                        .report_peer(&propagation_source, PeerAction::MidToleranceError);
+                    return;
+                }
+                self.received_topics.put(id.clone(), gs_msg.topics.clone());
+                self.network_globals
+                    .record_topic_activity(&gs_msg.topics, &propagation_source);
 
                 let client = self.network_globals.client(&propagation_source);
                 let agent_string = client.agent_string.unwrap_or_default();
@@ -443,15 +900,24 @@ impl Behaviour {
                     sequence_number: gs_msg.sequence_number,
                     agent_string: agent_string,
                     source: propagation_source,
-                    topics: gs_msg.topics,
-                    message: gs_msg.data,
+                    author,
+                    topics: self.network_globals.gossip_topics(&gs_msg.topics),
+                    message,
+                    raw,
                 });
             }
             GossipsubEvent::Subscribed { peer_id, topic } => {
+                self.network_globals
+                    .add_topic_peer(topic.clone(), peer_id.clone());
+                self.expand_patterns(&topic);
+                self.peer_manager.peer_subscribed(&peer_id, topic.clone());
                 self.events
                     .push(BehaviourEvent::PeerSubscribed(peer_id, topic));
             }
-            GossipsubEvent::Unsubscribed { .. } => {}
+            GossipsubEvent::Unsubscribed { peer_id, topic } => {
+                self.network_globals.remove_topic_peer(&topic, &peer_id);
+                self.peer_manager.peer_unsubscribed(&peer_id, topic);
+            }
         }
     }
 
@@ -629,22 +1095,29 @@ impl Behaviour {
                     PeerManagerEvent::MetaData(peer_id) => {
                         self.send_meta_data_request(peer_id);
                     }
-                    PeerManagerEvent::DisconnectPeer(peer_id) => {
+                    PeerManagerEvent::DisconnectPeer(peer_id, reason) => {
                         debug!(self.log, "PeerManager requested to disconnect a peer";
-                            "peer_id" => peer_id.to_string());
+                            "peer_id" => peer_id.to_string(), "reason" => reason.to_string());
                         // queue for disabling
                         self.peers_to_dc.push(peer_id.clone());
+                        // the goodbye reason is sent as an ssz encoded u64
+                        let reason: u64 = reason.into();
                         // send one goodbye
                         return Poll::Ready(NBAction::NotifyHandler {
                             peer_id,
                             handler: NotifyHandler::Any,
                             event: BehaviourHandlerIn::Shutdown(Some((
                                 RequestId::Behaviour,
-                                RPCRequest::Goodbye(vec![]),
-                                //RPCRequest::Goodbye(GoodbyeReason::Fault),
+                                RPCRequest::Goodbye(reason.to_le_bytes().to_vec()),
                             ))),
                         });
                     }
+                    PeerManagerEvent::BanPeer(peer_id, duration) => {
+                        // banning is handled by the libp2p service
+                        return Poll::Ready(NBAction::GenerateEvent(BehaviourEvent::BanPeer(
+                            peer_id, duration,
+                        )));
+                    }
                 },
                 Poll::Pending => break,
                 Poll::Ready(None) => break, // peer manager ended
@@ -774,13 +1247,57 @@ pub enum BehaviourEvent {
         agent_string: String,
         /// The peer from which we received this message, not the peer that published it.
         source: PeerId,
-        /// The topics that this message was sent on.
-        topics: Vec<TopicHash>,
+        /// The peer that published the message, if it signed it.
+        author: Option<PeerId>,
+        /// The topics that this message was sent on, as subscribed to.
+        topics: Vec<GossipTopic>,
         /// The message itself.
         message: Vec<u8>,
+        /// The message as received, before it was decoded, if traffic is captured.
+        raw: Option<Vec<u8>>,
     },
+    /// A message was published, as sent to the peers. Only emitted if traffic is captured.
+    PubsubPublished { topic: GossipTopic, data: Vec<u8> },
     /// Subscribed to peer for given topic
     PeerSubscribed(PeerId, TopicHash),
     /// Inform the network to send a Status to this peer.
     StatusPeer(PeerId),
+    /// The peer manager requested to ban a peer for the given duration.
+    BanPeer(PeerId, Duration),
+}
+
+/// The reasons a message could not be published.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PublishError {
+    /// No connected peer is subscribed to the topic.
+    InsufficientPeers,
+    /// The same message was already published on the topic within the duplicate cache time.
+    Duplicate,
+    /// The encoded message is larger than the maximum gossipsub message size.
+    TooLarge { size: usize, max_size: usize },
+    /// The message could not be encoded for the topic.
+    Encoding(String),
+}
+
+impl fmt::Display for PublishError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PublishError::InsufficientPeers => write!(f, "No peers subscribed to the topic"),
+            PublishError::Duplicate => write!(f, "Message already published"),
+            PublishError::TooLarge { size, max_size } => {
+                write!(f, "Message too large: {} > {} bytes", size, max_size)
+            }
+            PublishError::Encoding(e) => write!(f, "Could not encode message: {}", e),
+        }
+    }
+}
+
+/// Identifies a published message by its topic and data.
+fn published_message_hash(topic: &TopicHash, data: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    // length prefixed so that the topic and the data cannot be shifted into each other
+    hasher.input(&(topic.as_str().len() as u64).to_be_bytes());
+    hasher.input(topic.as_str().as_bytes());
+    hasher.input(data);
+    hasher.result().to_vec()
 }
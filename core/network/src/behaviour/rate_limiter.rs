@@ -0,0 +1,163 @@
+//! Limits the rate of the gossipsub messages each peer forwards on a topic.
+//!
+//! Every peer has a token bucket per limited topic, holding up to one second worth of messages and
+//! refilled continuously at the rate of the topic.
+
+use crate::{PeerId, TopicHash};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// The tokens of a peer on a topic.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Limits the messages per second forwarded by each peer on each topic.
+pub struct TopicRateLimiter {
+    /// The maximum number of messages per second of the limited topics.
+    rates: HashMap<TopicHash, u32>,
+    buckets: HashMap<(TopicHash, PeerId), Bucket>,
+}
+
+impl TopicRateLimiter {
+    pub fn new(rates: HashMap<TopicHash, u32>) -> Self {
+        TopicRateLimiter {
+            rates,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Counts a message forwarded by a peer on some topics. If the peer exceeded the rate of one
+    /// of them, returns that topic and the message is counted on none of them.
+    pub fn allow(&mut self, topics: &[TopicHash], peer_id: &PeerId) -> Result<(), TopicHash> {
+        self.allow_at(topics, peer_id, Instant::now())
+    }
+
+    fn allow_at(
+        &mut self,
+        topics: &[TopicHash],
+        peer_id: &PeerId,
+        now: Instant,
+    ) -> Result<(), TopicHash> {
+        // every limit is checked before taking a token from any bucket
+        for topic in topics {
+            if let Some(bucket) = self.refill(topic, peer_id, now) {
+                if bucket.tokens < 1.0 {
+                    return Err(topic.clone());
+                }
+            }
+        }
+        for topic in topics {
+            if let Some(bucket) = self.buckets.get_mut(&(topic.clone(), peer_id.clone())) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Refills the bucket of a peer on a topic, if the topic is limited.
+    fn refill(&mut self, topic: &TopicHash, peer_id: &PeerId, now: Instant) -> Option<&mut Bucket> {
+        let rate = match self.rates.get(topic) {
+            Some(rate) if *rate > 0 => f64::from(*rate),
+            _ => return None,
+        };
+        let bucket = self
+            .buckets
+            .entry((topic.clone(), peer_id.clone()))
+            .or_insert(Bucket {
+                tokens: rate,
+                updated: now,
+            });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+        bucket.updated = now;
+        Some(bucket)
+    }
+
+    /// Forgets the buckets of a disconnected peer.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.buckets.retain(|(_, peer), _| peer != peer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limiter() -> TopicRateLimiter {
+        let mut rates = HashMap::new();
+        rates.insert(TopicHash::from_raw("blocks"), 2);
+        rates.insert(TopicHash::from_raw("attestations"), 1);
+        rates.insert(TopicHash::from_raw("unlimited"), 0);
+        TopicRateLimiter::new(rates)
+    }
+
+    fn allow(
+        limiter: &mut TopicRateLimiter,
+        topic: &TopicHash,
+        peer_id: &PeerId,
+        now: Instant,
+    ) -> bool {
+        limiter.allow_at(&[topic.clone()], peer_id, now).is_ok()
+    }
+
+    #[test]
+    fn test_rate_per_peer_and_topic() {
+        let mut limiter = limiter();
+        let blocks = TopicHash::from_raw("blocks");
+        let (peer, other_peer) = (PeerId::random(), PeerId::random());
+        let now = Instant::now();
+
+        assert!(allow(&mut limiter, &blocks, &peer, now));
+        assert!(allow(&mut limiter, &blocks, &peer, now));
+        assert!(!allow(&mut limiter, &blocks, &peer, now));
+        assert!(allow(&mut limiter, &blocks, &other_peer, now));
+
+        // topics without a rate are not limited
+        for topic in &["unlimited", "voluntary_exits"] {
+            let topic = TopicHash::from_raw(*topic);
+            assert!((0..100).all(|_| allow(&mut limiter, &topic, &peer, now)));
+        }
+    }
+
+    #[test]
+    fn test_refill() {
+        let mut limiter = limiter();
+        let blocks = TopicHash::from_raw("blocks");
+        let peer = PeerId::random();
+        let now = Instant::now();
+        assert!(allow(&mut limiter, &blocks, &peer, now));
+        assert!(allow(&mut limiter, &blocks, &peer, now));
+
+        let later = now + Duration::from_millis(500);
+        assert!(allow(&mut limiter, &blocks, &peer, later));
+        assert!(!allow(&mut limiter, &blocks, &peer, later));
+
+        // at most one second worth of messages is saved up
+        let much_later = later + Duration::from_secs(10);
+        assert!(allow(&mut limiter, &blocks, &peer, much_later));
+        assert!(allow(&mut limiter, &blocks, &peer, much_later));
+        assert!(!allow(&mut limiter, &blocks, &peer, much_later));
+
+        limiter.remove_peer(&peer);
+        assert!(limiter.buckets.is_empty());
+    }
+
+    #[test]
+    fn test_all_topics_checked_before_counting() {
+        let mut limiter = limiter();
+        let blocks = TopicHash::from_raw("blocks");
+        let attestations = TopicHash::from_raw("attestations");
+        let peer = PeerId::random();
+        let now = Instant::now();
+        let both = [blocks.clone(), attestations.clone()];
+
+        assert_eq!(limiter.allow_at(&both, &peer, now), Ok(()));
+        assert_eq!(limiter.allow_at(&both, &peer, now), Err(attestations));
+        // the rejected message did not take a token on blocks
+        assert!(allow(&mut limiter, &blocks, &peer, now));
+        assert!(!allow(&mut limiter, &blocks, &peer, now));
+    }
+}
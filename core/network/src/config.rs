@@ -1,17 +1,30 @@
 extern crate target_info;
+use crate::peer_manager::{FilterEntry, PeerScoreParams, PeerScoreThresholds};
 use crate::Enr;
 use crate::{error, DEFAULT_CLIENT_NAME};
 use discv5::{Discv5Config, Discv5ConfigBuilder};
-use libp2p::gossipsub::{GossipsubConfig, GossipsubConfigBuilder, GossipsubMessage, MessageId};
+use libp2p::gossipsub::{
+    GossipsubConfig, GossipsubConfigBuilder, GossipsubMessage, MessageId, TopicHash,
+};
 use libp2p::Multiaddr;
 use serde_derive::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Duration;
 use target_info::Target;
 
 pub const GOSSIP_MAX_SIZE: usize = 1_048_576;
 
+/// The message id domain of eth2 messages whose data is valid snappy.
+const MESSAGE_DOMAIN_VALID_SNAPPY: [u8; 4] = [1, 0, 0, 0];
+/// The message id domain of eth2 messages whose data is not valid snappy.
+const MESSAGE_DOMAIN_INVALID_SNAPPY: [u8; 4] = [0, 0, 0, 0];
+/// The number of bytes of the hash used as an eth2 message id.
+const ETH2_MESSAGE_ID_LENGTH: usize = 20;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(default)]
 /// Network configuration
@@ -47,17 +60,54 @@ pub struct Config {
     /// Target number of connected peers.
     pub max_peers: usize,
 
+    /// Maximum number of inbound peers connected from the same IP address. 0 disables the limit.
+    pub max_peers_per_ip: usize,
+
+    /// Maximum number of inbound peers connected from the same /24 (IPv4) or /64 (IPv6) network.
+    /// 0 disables the limit.
+    pub max_peers_per_subnet: usize,
+
+    /// Address of the HTTP API exposing the node's state as JSON. `None` disables it.
+    pub http_address: Option<std::net::SocketAddr>,
+
+    /// Address of the WebSocket JSON-RPC API. `None` disables it.
+    pub ws_address: Option<std::net::SocketAddr>,
+
+    /// Address of the HTTP server exposing prometheus metrics on `/metrics`. `None` disables it.
+    pub metrics_address: Option<std::net::SocketAddr>,
+
+    /// Records all gossip and RPC traffic to capture files in `network_dir/captures`.
+    pub capture_traffic: bool,
+
+    /// The size in bytes after which a new capture file is started.
+    pub capture_file_size: u64,
+
     /// A secp256k1 secret key, as bytes in ASCII-encoded hex.
     ///
     /// With or without `0x` prefix.
     #[serde(skip)]
     pub secret_key_hex: Option<String>,
 
-    /// Gossipsub configuration parameters.
+    /// Gossipsub parameters that can be set from a config file.
+    pub gossipsub: GossipParams,
+
+    /// Discv5 parameters that can be set from a config file.
+    pub discovery: DiscoveryParams,
+
+    /// Disables the gossip peer scoring.
+    pub disable_peer_scoring: bool,
+
+    /// Gossip peer scoring parameters, including the per-topic parameters.
+    pub peer_score_params: PeerScoreParams,
+
+    /// The gossip scores below which peers are restricted.
+    pub peer_score_thresholds: PeerScoreThresholds,
+
+    /// Gossipsub configuration, built from `gossipsub` by `update_protocol_configs`.
     #[serde(skip)]
     pub gs_config: GossipsubConfig,
 
-    /// Discv5 configuration parameters.
+    /// Discv5 configuration, built from `discovery` by `update_protocol_configs`.
     #[serde(skip)]
     pub discv5_config: Discv5Config,
 
@@ -70,13 +120,45 @@ pub struct Config {
     /// Disables the discovery protocol from starting.
     pub disable_discovery: bool,
 
-    /// List of extra topics to initially subscribe to as strings.
+    /// Peer ids and IP networks that are allowed to connect. If empty, any peer that is not
+    /// denied may connect.
+    pub allow_list: Vec<FilterEntry>,
+
+    /// Peer ids and IP networks that are never allowed to connect.
+    pub deny_list: Vec<FilterEntry>,
+
+    /// List of extra topics to initially subscribe to as strings. Topics containing `*` are
+    /// patterns, which subscribe to the matching topics peers subscribe to.
     pub topics: Vec<String>,
 
-    /// Introduces randomization in network propagation of messages. This should only be set for
-    /// testing purposes and will likely be removed in future versions.
+    /// The maximum number of topics a topic pattern subscribes to.
+    pub max_pattern_topics: usize,
+
+    /// The time to live in milliseconds of the messages of topics, by topic. Messages of these
+    /// topics are published in a timestamp envelope, and received messages older than their time
+    /// to live are dropped and not propagated. All the peers of a topic must agree on its entry.
+    pub message_ttls: HashMap<String, u64>,
+
+    /// The symmetric keys of private topics, by topic. Messages of these topics are encrypted with
+    /// the first key, and received messages are decrypted with the key they name. Messages that
+    /// cannot be decrypted are ignored and not delivered. Peers without keys for a topic deliver
+    /// its messages undecrypted and propagate them, so all the peers of a private topic should
+    /// have its keys. The keys are redacted when the config is dumped.
+    pub topic_keys: HashMap<String, Vec<TopicKey>>,
+
+    /// Introduces randomization in network propagation of messages: the percentage of published
+    /// and propagated gossipsub messages that are sent, the others are dropped. This should only
+    /// be set for testing purposes and will likely be removed in future versions.
     // TODO: Remove this functionality for mainnet
     pub propagation_percentage: Option<u8>,
+
+    /// Delays the published and propagated gossipsub messages that are not dropped by a random
+    /// duration of up to this many milliseconds. 0 disables delays. For testing purposes only.
+    pub propagation_delay_ms: u64,
+
+    /// Seeds the random decisions of `propagation_percentage` and `propagation_delay_ms`, so that
+    /// simulation runs are reproducible.
+    pub propagation_seed: Option<u64>,
 }
 
 impl Default for Config {
@@ -93,39 +175,10 @@ impl Default for Config {
         network_dir.push(format!(".{}", DEFAULT_CLIENT_NAME));
         network_dir.push("network");
 
-        // The function used to generate a gossipsub message id
-        // We use base64(SHA256(data)) for content addressing
-        let gossip_message_id = |message: &GossipsubMessage| {
-            MessageId(base64::encode_config(
-                &Sha256::digest(&message.data),
-                base64::URL_SAFE,
-            ))
-        };
-
-        // gossipsub configuration
-        // Note: The topics by default are sent as plain strings. Hashes are an optional
-        // parameter.
-        let gs_config = GossipsubConfigBuilder::new()
-            .max_transmit_size(GOSSIP_MAX_SIZE)
-            .heartbeat_interval(Duration::from_secs(1))
-            .manual_propagation() // require validation before propagation
-            .no_source_id()
-            .message_id_fn(gossip_message_id)
-            .build();
-
-        // discv5 configuration
-        let discv5_config = Discv5ConfigBuilder::new()
-            .enable_packet_filter()
-            .session_cache_capacity(1000)
-            .request_timeout(Duration::from_secs(4))
-            .request_retries(2)
-            .enr_peer_update_min(2) // prevents NAT's should be raised for mainnet
-            .query_parallelism(5)
-            .query_timeout(Duration::from_secs(30))
-            .query_peer_timeout(Duration::from_secs(2))
-            .ip_limit() // limits /24 IP's in buckets.
-            .ping_interval(Duration::from_secs(300))
-            .build();
+        let gossipsub = GossipParams::default();
+        let discovery = DiscoveryParams::default();
+        let gs_config = gossipsub.build();
+        let discv5_config = discovery.build();
 
         Config {
             agent_version,
@@ -138,14 +191,33 @@ impl Default for Config {
             enr_udp_port: None,
             enr_tcp_port: None,
             max_peers: 50,
+            max_peers_per_ip: 3,
+            max_peers_per_subnet: 10,
+            http_address: None,
+            ws_address: None,
+            metrics_address: None,
+            capture_traffic: false,
+            capture_file_size: 64 * 1024 * 1024,
             secret_key_hex: None,
+            gossipsub,
+            discovery,
+            disable_peer_scoring: false,
+            peer_score_params: PeerScoreParams::default(),
+            peer_score_thresholds: PeerScoreThresholds::default(),
             gs_config,
             discv5_config,
             boot_nodes: vec![],
             libp2p_nodes: vec![],
             disable_discovery: false,
+            allow_list: vec![],
+            deny_list: vec![],
             topics: vec![],
+            max_pattern_topics: 64,
+            message_ttls: HashMap::new(),
+            topic_keys: HashMap::new(),
             propagation_percentage: None,
+            propagation_delay_ms: 0,
+            propagation_seed: None,
         }
     }
 }
@@ -154,6 +226,359 @@ impl Config {
     pub fn new() -> Self {
         Config::default()
     }
+
+    /// Rebuilds `gs_config` and `discv5_config` after `gossipsub` or `discovery` changed.
+    pub fn update_protocol_configs(&mut self) {
+        self.gs_config = self.gossipsub.build();
+        self.discv5_config = self.discovery.build();
+    }
+
+    /// Computes gossipsub message ids with `message_id_fn` instead of the configured strategy.
+    /// See `MessageIdFn` for why it cannot be a capturing closure.
+    pub fn set_message_id_fn(&mut self, message_id_fn: fn(&GossipsubMessage) -> MessageId) {
+        self.gossipsub.custom_message_id = Some(MessageIdFn(message_id_fn));
+        self.gs_config = self.gossipsub.build();
+    }
+}
+
+/// How the id of a gossipsub message, used to drop duplicates, is computed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageIdStrategy {
+    /// base64(SHA256(data)). Identical payloads on different topics get the same id.
+    DataHash,
+    /// base64(SHA256(topics + data)).
+    TopicDataHash,
+    /// The eth2 rule: the first 20 bytes of SHA256(domain + data), where the data is snappy
+    /// decompressed if possible and the domain tells whether it was, encoded in base64.
+    Eth2Snappy,
+}
+
+impl MessageIdStrategy {
+    fn message_id_fn(self) -> fn(&GossipsubMessage) -> MessageId {
+        match self {
+            MessageIdStrategy::DataHash => |message: &GossipsubMessage| data_hash(&message.data),
+            MessageIdStrategy::TopicDataHash => {
+                |message: &GossipsubMessage| topic_data_hash(&message.topics, &message.data)
+            }
+            MessageIdStrategy::Eth2Snappy => {
+                |message: &GossipsubMessage| eth2_snappy_hash(&message.data)
+            }
+        }
+    }
+}
+
+impl FromStr for MessageIdStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "data_hash" => Ok(MessageIdStrategy::DataHash),
+            "topic_data_hash" => Ok(MessageIdStrategy::TopicDataHash),
+            "eth2_snappy" => Ok(MessageIdStrategy::Eth2Snappy),
+            _ => Err(format!("Unknown message id strategy: {}", s)),
+        }
+    }
+}
+
+/// Whether gossipsub messages carry the peer id of their author and a signature by it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningPolicy {
+    /// Messages carry neither their author nor a signature, only the forwarding peer is known.
+    /// Received messages are delivered as they are, including the envelopes of signed messages.
+    Anonymous,
+    /// Published messages are signed. Received signed messages are delivered with their author
+    /// once verified, unsigned messages without an author.
+    Signed,
+    /// Published messages are signed and received messages must be signed by their author.
+    Strict,
+}
+
+impl FromStr for SigningPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "anonymous" => Ok(SigningPolicy::Anonymous),
+            "signed" => Ok(SigningPolicy::Signed),
+            "strict" => Ok(SigningPolicy::Strict),
+            _ => Err(format!("Unknown signing policy: {}", s)),
+        }
+    }
+}
+
+/// A message id function supplied through the Rust API.
+///
+/// Gossipsub takes a plain `fn` pointer (see `Behaviour`), so closures capturing their
+/// environment cannot be used; state must live in statics.
+#[derive(Clone, Copy)]
+pub struct MessageIdFn(pub fn(&GossipsubMessage) -> MessageId);
+
+impl fmt::Debug for MessageIdFn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("MessageIdFn")
+    }
+}
+
+fn encode_message_id(hash: &[u8]) -> MessageId {
+    MessageId(base64::encode_config(hash, base64::URL_SAFE))
+}
+
+fn data_hash(data: &[u8]) -> MessageId {
+    encode_message_id(&Sha256::digest(data))
+}
+
+fn topic_data_hash(topics: &[TopicHash], data: &[u8]) -> MessageId {
+    let mut hasher = Sha256::new();
+    for topic in topics {
+        // length prefixed so that the topics and the data cannot be shifted into each other
+        hasher.input(&(topic.as_str().len() as u64).to_le_bytes());
+        hasher.input(topic.as_str().as_bytes());
+    }
+    hasher.input(data);
+    encode_message_id(&hasher.result())
+}
+
+/// Messages whose snappy header claims more than `GOSSIP_MAX_SIZE` bytes are hashed as invalid
+/// snappy without being decompressed. Message ids are computed before any validation, and the id
+/// function cannot capture the configured `max_transmit_size`.
+fn eth2_snappy_hash(data: &[u8]) -> MessageId {
+    let mut hasher = Sha256::new();
+    let decompressed = match snap::raw::decompress_len(data) {
+        Ok(size) if size <= GOSSIP_MAX_SIZE => snap::raw::Decoder::new().decompress_vec(data).ok(),
+        _ => None,
+    };
+    match decompressed {
+        Some(decompressed) => {
+            hasher.input(&MESSAGE_DOMAIN_VALID_SNAPPY);
+            hasher.input(&decompressed);
+        }
+        None => {
+            hasher.input(&MESSAGE_DOMAIN_INVALID_SNAPPY);
+            hasher.input(data);
+        }
+    }
+    encode_message_id(&hasher.result()[..ETH2_MESSAGE_ID_LENGTH])
+}
+
+/// Gossipsub parameters that can be set from a config file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GossipParams {
+    /// The maximum byte size of a gossipsub message.
+    pub max_transmit_size: usize,
+    /// Time between gossipsub heartbeats in milliseconds.
+    pub heartbeat_interval_ms: u64,
+    /// Target number of peers in the mesh of a topic (D).
+    pub mesh_n: usize,
+    /// Minimum number of peers in a mesh before more are grafted (D_low).
+    pub mesh_n_low: usize,
+    /// Maximum number of peers in a mesh before some are pruned (D_high).
+    pub mesh_n_high: usize,
+    /// Number of peers outside the mesh that gossip is emitted to (D_lazy).
+    pub gossip_lazy: usize,
+    /// Number of heartbeats messages are kept in the message cache for.
+    pub history_length: usize,
+    /// Number of heartbeats of the message cache advertised in gossip.
+    pub history_gossip: usize,
+    /// Milliseconds a fanout topic is kept after the last message published on it.
+    pub fanout_ttl_ms: u64,
+    /// Milliseconds message ids are remembered to drop duplicates.
+    pub duplicate_cache_time_ms: u64,
+    /// How message ids are computed.
+    pub message_id: MessageIdStrategy,
+    /// Whether published messages carry their author and a signature, and whether received
+    /// messages must. All the peers of a network must either sign messages or not, anonymous
+    /// messages starting with the marker of signed ones are invalid for the others.
+    pub signing_policy: SigningPolicy,
+    /// Limits of the messages received on topics, by topic.
+    pub topic_limits: HashMap<String, TopicLimits>,
+    /// A message id function taking precedence over `message_id`, set with
+    /// `Config::set_message_id_fn`.
+    #[serde(skip)]
+    pub custom_message_id: Option<MessageIdFn>,
+}
+
+impl Default for GossipParams {
+    fn default() -> Self {
+        GossipParams {
+            max_transmit_size: GOSSIP_MAX_SIZE,
+            heartbeat_interval_ms: 1000,
+            mesh_n: 6,
+            mesh_n_low: 4,
+            mesh_n_high: 12,
+            gossip_lazy: 6,
+            history_length: 5,
+            history_gossip: 3,
+            fanout_ttl_ms: 60_000,
+            duplicate_cache_time_ms: 60_000,
+            message_id: MessageIdStrategy::DataHash,
+            signing_policy: SigningPolicy::Anonymous,
+            topic_limits: HashMap::new(),
+            custom_message_id: None,
+        }
+    }
+}
+
+/// Limits of the messages received on a topic. Messages exceeding them are dropped and the peer
+/// that forwarded them is reported.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicLimits {
+    /// The maximum byte size of a decoded message. 0 only applies `max_transmit_size`.
+    pub max_message_size: usize,
+    /// The maximum number of messages per second each peer forwards. 0 disables the limit.
+    pub max_messages_per_second: u32,
+}
+
+/// A symmetric key of a private topic, shared out of band with the peers allowed to read it.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopicKey {
+    /// Identifies the key in encrypted messages, so that keys can be rotated.
+    pub id: u32,
+    /// The 32 byte ChaCha20-Poly1305 key, hex encoded.
+    pub key_hex: String,
+}
+
+impl fmt::Debug for TopicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TopicKey")
+            .field("id", &self.id)
+            .field("key_hex", &"<redacted>")
+            .finish()
+    }
+}
+
+impl GossipParams {
+    /// Checks that the parameters are consistent with each other.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.mesh_n_low == 0 {
+            return Err("The gossipsub mesh low degree must be positive".into());
+        }
+        if self.mesh_n_low > self.mesh_n || self.mesh_n > self.mesh_n_high {
+            return Err(format!(
+                "Gossipsub mesh degrees must satisfy low <= target <= high, got {} <= {} <= {}",
+                self.mesh_n_low, self.mesh_n, self.mesh_n_high
+            ));
+        }
+        if self.history_gossip == 0 || self.history_gossip > self.history_length {
+            return Err(format!(
+                "The gossipsub history gossip ({}) must be between 1 and the history length ({})",
+                self.history_gossip, self.history_length
+            ));
+        }
+        if self.heartbeat_interval_ms == 0 {
+            return Err("The gossipsub heartbeat interval must be positive".into());
+        }
+        if self.max_transmit_size == 0 {
+            return Err("The gossipsub max transmit size must be positive".into());
+        }
+        Ok(())
+    }
+
+    /// Builds the gossipsub configuration.
+    pub fn build(&self) -> GossipsubConfig {
+        // The function used to generate a gossipsub message id
+        let gossip_message_id = match self.custom_message_id {
+            Some(MessageIdFn(message_id_fn)) => message_id_fn,
+            None => self.message_id.message_id_fn(),
+        };
+
+        // Note: The topics by default are sent as plain strings. Hashes are an optional
+        // parameter.
+        let mut builder = GossipsubConfigBuilder::new();
+        builder
+            .max_transmit_size(self.max_transmit_size)
+            .heartbeat_interval(Duration::from_millis(self.heartbeat_interval_ms))
+            .mesh_n(self.mesh_n)
+            .mesh_n_low(self.mesh_n_low)
+            .mesh_n_high(self.mesh_n_high)
+            .gossip_lazy(self.gossip_lazy)
+            .history_length(self.history_length)
+            .history_gossip(self.history_gossip)
+            .fanout_ttl(Duration::from_millis(self.fanout_ttl_ms))
+            .duplicate_cache_time(Duration::from_millis(self.duplicate_cache_time_ms))
+            .manual_propagation() // require validation before propagation
+            .message_id_fn(gossip_message_id);
+        // anonymous messages do not carry the peer id of their author
+        if self.signing_policy == SigningPolicy::Anonymous {
+            builder.no_source_id();
+        }
+        builder.build()
+    }
+}
+
+/// Discv5 parameters that can be set from a config file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscoveryParams {
+    /// Filters out packets from IPs sending too many of them.
+    pub enable_packet_filter: bool,
+    /// The number of sessions kept with other nodes.
+    pub session_cache_capacity: usize,
+    /// Seconds to wait for a response to a request.
+    pub request_timeout_secs: u64,
+    /// The number of times a request is retried.
+    pub request_retries: u8,
+    /// The number of peers that must agree on our external address before the ENR is updated.
+    // prevents NAT's should be raised for mainnet
+    pub enr_peer_update_min: usize,
+    /// Updates the local ENR with the external address reported by peers.
+    pub enr_update: bool,
+    /// The number of peers queried in parallel.
+    pub query_parallelism: usize,
+    /// Seconds after which a query ends.
+    pub query_timeout_secs: u64,
+    /// Seconds to wait for a peer taking part in a query.
+    pub query_peer_timeout_secs: u64,
+    /// Limits the number of nodes from the same /24 in the routing table buckets.
+    pub ip_limit: bool,
+    /// Seconds between pings of connected nodes.
+    pub ping_interval_secs: u64,
+}
+
+impl Default for DiscoveryParams {
+    fn default() -> Self {
+        DiscoveryParams {
+            enable_packet_filter: true,
+            session_cache_capacity: 1000,
+            request_timeout_secs: 4,
+            request_retries: 2,
+            enr_peer_update_min: 2,
+            enr_update: true,
+            query_parallelism: 5,
+            query_timeout_secs: 30,
+            query_peer_timeout_secs: 2,
+            ip_limit: true,
+            ping_interval_secs: 300,
+        }
+    }
+}
+
+impl DiscoveryParams {
+    /// Builds the discv5 configuration.
+    pub fn build(&self) -> Discv5Config {
+        let mut builder = Discv5ConfigBuilder::new();
+        builder
+            .session_cache_capacity(self.session_cache_capacity)
+            .request_timeout(Duration::from_secs(self.request_timeout_secs))
+            .request_retries(self.request_retries)
+            .enr_peer_update_min(self.enr_peer_update_min)
+            .query_parallelism(self.query_parallelism)
+            .query_timeout(Duration::from_secs(self.query_timeout_secs))
+            .query_peer_timeout(Duration::from_secs(self.query_peer_timeout_secs))
+            .ping_interval(Duration::from_secs(self.ping_interval_secs));
+        if self.enable_packet_filter {
+            builder.enable_packet_filter();
+        }
+        if self.ip_limit {
+            builder.ip_limit();
+        }
+        let mut discv5_config = builder.build();
+        discv5_config.enr_update = self.enr_update;
+        discv5_config
+    }
 }
 
 pub fn unused_port(transport: &str) -> error::Result<u16> {
@@ -183,3 +608,62 @@ pub fn unused_port(transport: &str) -> error::Result<u16> {
     };
     Ok(local_addr.port())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gossip_params_validation() {
+        assert!(GossipParams::default().validate().is_ok());
+
+        let mut params = GossipParams::default();
+        params.mesh_n_low = params.mesh_n + 1;
+        assert!(params.validate().is_err());
+
+        let mut params = GossipParams::default();
+        params.mesh_n_high = params.mesh_n - 1;
+        assert!(params.validate().is_err());
+
+        let mut params = GossipParams::default();
+        params.history_gossip = params.history_length + 1;
+        assert!(params.validate().is_err());
+
+        let mut params = GossipParams::default();
+        params.heartbeat_interval_ms = 0;
+        assert!(params.validate().is_err());
+    }
+
+    #[test]
+    fn test_message_ids() {
+        let blocks = [TopicHash::from_raw("blocks")];
+        let attestations = [TopicHash::from_raw("attestations")];
+
+        assert_eq!(data_hash(b"payload"), data_hash(b"payload"));
+        assert_ne!(
+            topic_data_hash(&blocks, b"payload"),
+            topic_data_hash(&attestations, b"payload")
+        );
+
+        // the eth2 id of a snappy message is computed over the decompressed data
+        let compressed = snap::raw::Encoder::new().compress_vec(b"payload").unwrap();
+        let mut expected = Sha256::new();
+        expected.input(&MESSAGE_DOMAIN_VALID_SNAPPY);
+        expected.input(b"payload");
+        assert_eq!(
+            eth2_snappy_hash(&compressed),
+            encode_message_id(&expected.result()[..ETH2_MESSAGE_ID_LENGTH])
+        );
+        assert_ne!(eth2_snappy_hash(b"payload"), eth2_snappy_hash(&compressed));
+
+        // a snappy header claiming about 4 GB is hashed as invalid snappy
+        let oversized = [0xf0, 0xff, 0xff, 0xff, 0x0f, 0x00];
+        let mut expected = Sha256::new();
+        expected.input(&MESSAGE_DOMAIN_INVALID_SNAPPY);
+        expected.input(&oversized);
+        assert_eq!(
+            eth2_snappy_hash(&oversized),
+            encode_message_id(&expected.result()[..ETH2_MESSAGE_ID_LENGTH])
+        );
+    }
+}
@@ -7,6 +7,7 @@ pub use enr::CombinedKey;
 pub use enr_ext::{CombinedKeyExt, EnrExt};
 pub use libp2p::core::identity::Keypair;
 
+use crate::metrics;
 use crate::types::{EnrForkId, SubnetId};
 use crate::{error, Enr, NetworkConfig, NetworkGlobals};
 use discv5::{enr::NodeId, Discv5, Discv5Event};
@@ -18,7 +19,7 @@ use lru::LruCache;
 use slog::{crit, debug, info, trace, warn};
 use std::{
     collections::VecDeque,
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
     path::Path,
     pin::Pin,
     sync::Arc,
@@ -177,6 +178,12 @@ impl Discovery {
 
         // Add bootnodes to routing table
         for bootnode_enr in config.boot_nodes.clone() {
+            if !network_globals
+                .peer_allowed(&bootnode_enr.peer_id(), enr_ip(&bootnode_enr).as_ref())
+            {
+                debug!(log, "Boot node rejected by the peer filter"; "peer_id" => format!("{}", bootnode_enr.peer_id()));
+                continue;
+            }
             debug!(
                 log,
                 "Adding node to routing table";
@@ -242,6 +249,11 @@ impl Discovery {
 
     /// Add an ENR to the routing table of the discovery mechanism.
     pub fn add_enr(&mut self, enr: Enr) {
+        if !self.enr_allowed(&enr) {
+            debug!(self.log, "ENR rejected by the peer filter"; "peer_id" => format!("{}", enr.peer_id()));
+            return;
+        }
+
         // add the enr to seen caches
         self.cached_enrs.put(enr.peer_id(), enr.clone());
 
@@ -292,6 +304,13 @@ impl Discovery {
 
     /* Internal Functions */
 
+    /// Returns true if the peer and the address advertised in its ENR pass the allow and deny
+    /// lists.
+    fn enr_allowed(&self, enr: &Enr) -> bool {
+        self.network_globals
+            .peer_allowed(&enr.peer_id(), enr_ip(enr).as_ref())
+    }
+
     /// Consume the discovery queue and initiate queries when applicable.
     ///
     /// This also sanitizes the queue removing out-dated queries.
@@ -346,6 +365,7 @@ impl Discovery {
             .map(|v| QueryResult(query, v));
 
         // Add the future to active queries, to be executed.
+        metrics::inc_counter(&metrics::DISCOVERY_QUERIES);
         self.active_queries.push(Box::pin(query_future));
     }
 
@@ -361,10 +381,15 @@ impl Discovery {
                         }
                         Ok(r) => {
                             debug!(self.log, "Discovery query completed"; "peers_found" => r.len());
+                            metrics::inc_counter_by(
+                                &metrics::DISCOVERY_PEERS_FOUND,
+                                r.len() as i64,
+                            );
                             return Some((None, r));
                         }
                         Err(e) => {
                             warn!(self.log, "Discovery query failed"; "error" => e.to_string());
+                            metrics::inc_counter(&metrics::DISCOVERY_QUERY_FAILURES);
                         }
                     }
                 }
@@ -388,7 +413,9 @@ impl Discovery {
         self.process_queue();
 
         // Drive the queries and return any results from completed queries
-        if let Some((min_ttl, result)) = self.poll_queries(cx) {
+        if let Some((min_ttl, mut result)) = self.poll_queries(cx) {
+            // drop the peers that are not permitted by the peer filter
+            result.retain(|enr| self.enr_allowed(enr));
             // cache the found ENR's
             for enr in result.iter().cloned() {
                 self.cached_enrs.put(enr.peer_id(), enr);
@@ -446,3 +473,10 @@ impl Discovery {
         Poll::Pending
     }
 }
+
+/// Returns the IP address advertised in an ENR, preferring IPv4.
+fn enr_ip(enr: &Enr) -> Option<IpAddr> {
+    enr.ip()
+        .map(IpAddr::V4)
+        .or_else(|| enr.ip6().map(IpAddr::V6))
+}
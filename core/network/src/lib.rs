@@ -3,22 +3,33 @@ mod config;
 mod discovery;
 mod executor;
 mod hashset_delay;
+pub mod metrics;
 mod peer_manager;
 pub mod rpc;
 mod service;
 pub mod types;
 
-pub use crate::types::{error, Enr, EnrBitfield, EnrForkId, GossipTopic, NetworkGlobals};
-pub use behaviour::{BehaviourEvent, PeerRequestId, Request, Response};
+pub use crate::types::{
+    error, Enr, EnrBitfield, EnrForkId, Eth2Kind, Eth2Topic, GossipEncoding, GossipTopic,
+    NetworkGlobals, TopicPattern,
+};
+pub use behaviour::{BehaviourEvent, PeerRequestId, PublishError, Request, Response};
 pub use config::unused_port;
 pub use config::Config as NetworkConfig;
+pub use config::{
+    DiscoveryParams, GossipParams, MessageIdFn, MessageIdStrategy, SigningPolicy, TopicKey,
+    TopicLimits,
+};
 pub use discovery::{CombinedKeyExt, EnrExt};
 pub use discv5;
 pub use executor::TaskExecutor;
 pub use hashset_delay::HashSetDelay;
-pub use libp2p::gossipsub::{MessageId, Topic, TopicHash};
+pub use libp2p::gossipsub::{GossipsubMessage, MessageId, Topic, TopicHash};
 pub use libp2p::{core::ConnectedPoint, PeerId, Swarm};
 pub use libp2p::{multiaddr, Multiaddr};
-pub use peer_manager::{client::Client, PeerDB, PeerInfo};
+pub use peer_manager::{
+    client::Client, ConnectionDirection, ConnectionGate, FilterEntry, FilterList, IpCidr, PeerDB,
+    PeerFilter, PeerInfo, PeerScoreParams, PeerScoreThresholds, TopicScoreParams,
+};
 pub use service::{Libp2pEvent, Service, NETWORK_KEY_FILENAME};
 pub const DEFAULT_CLIENT_NAME: &str = "mothra";
@@ -0,0 +1,220 @@
+//! Prometheus metrics of the network, gossip and RPC activity.
+//!
+//! Metrics are registered in the default prometheus registry. Failing to create a metric never
+//! affects the network, the metric is simply not recorded.
+
+use crate::peer_manager::{client::ClientKind, Connected};
+use crate::NetworkGlobals;
+use lazy_static::lazy_static;
+pub use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Result,
+    TextEncoder,
+};
+use std::collections::HashMap;
+use std::time::Duration;
+
+lazy_static! {
+    /*
+     * Peers
+     */
+    pub static ref PEERS_CONNECTED: Result<IntGaugeVec> = try_create_int_gauge_vec(
+        "mothra_peers_connected",
+        "Number of connected peers by connection direction and client kind",
+        &["direction", "client"]
+    );
+
+    /*
+     * Gossip
+     */
+    pub static ref GOSSIP_MESSAGES_RECEIVED: Result<IntCounterVec> = try_create_int_counter_vec(
+        "mothra_gossip_messages_received_total",
+        "Number of gossipsub messages received per topic",
+        &["topic"]
+    );
+    pub static ref GOSSIP_MESSAGES_PUBLISHED: Result<IntCounterVec> = try_create_int_counter_vec(
+        "mothra_gossip_messages_published_total",
+        "Number of gossipsub messages published per topic",
+        &["topic"]
+    );
+    pub static ref GOSSIP_MESSAGES_PROPAGATED: Result<IntCounterVec> = try_create_int_counter_vec(
+        "mothra_gossip_messages_propagated_total",
+        "Number of received gossipsub messages propagated per topic",
+        &["topic"]
+    );
+    pub static ref GOSSIP_STALE_MESSAGES: Result<IntCounterVec> = try_create_int_counter_vec(
+        "mothra_gossip_stale_messages_total",
+        "Number of received gossipsub messages dropped for being older than their time to live, per topic",
+        &["topic"]
+    );
+    pub static ref GOSSIP_UNDECRYPTABLE_MESSAGES: Result<IntCounterVec> = try_create_int_counter_vec(
+        "mothra_gossip_undecryptable_messages_total",
+        "Number of received gossipsub messages of private topics ignored for not decrypting, per topic",
+        &["topic"]
+    );
+    pub static ref GOSSIP_LIMIT_VIOLATIONS: Result<IntCounterVec> = try_create_int_counter_vec(
+        "mothra_gossip_limit_violations_total",
+        "Number of received gossipsub messages dropped for exceeding the size or rate limit of their topic",
+        &["topic", "limit"]
+    );
+    pub static ref GOSSIP_FAULTS_INJECTED: Result<IntCounterVec> = try_create_int_counter_vec(
+        "mothra_gossip_faults_injected_total",
+        "Number of published or propagated gossipsub messages dropped or delayed for testing",
+        &["kind", "fault"]
+    );
+
+    /*
+     * RPC
+     */
+    pub static ref RPC_REQUESTS_RECEIVED: Result<IntCounterVec> = try_create_int_counter_vec(
+        "mothra_rpc_requests_received_total",
+        "Number of RPC requests received per protocol",
+        &["protocol"]
+    );
+    pub static ref RPC_REQUESTS_SENT: Result<IntCounterVec> = try_create_int_counter_vec(
+        "mothra_rpc_requests_sent_total",
+        "Number of RPC requests sent per protocol",
+        &["protocol"]
+    );
+    pub static ref RPC_ERRORS: Result<IntCounterVec> = try_create_int_counter_vec(
+        "mothra_rpc_errors_total",
+        "Number of RPC errors per protocol, error and direction of the request",
+        &["protocol", "error", "direction"]
+    );
+    pub static ref RPC_RESPONSE_TIME: Result<HistogramVec> = try_create_histogram_vec(
+        "mothra_rpc_response_time_seconds",
+        "Time between opening an outbound RPC substream and receiving the response, per protocol",
+        &["protocol"]
+    );
+
+    /*
+     * Discovery
+     */
+    pub static ref DISCOVERY_QUERIES: Result<IntCounter> = try_create_int_counter(
+        "mothra_discovery_queries_total",
+        "Number of discovery queries started"
+    );
+    pub static ref DISCOVERY_QUERY_FAILURES: Result<IntCounter> = try_create_int_counter(
+        "mothra_discovery_query_failures_total",
+        "Number of discovery queries that failed"
+    );
+    pub static ref DISCOVERY_PEERS_FOUND: Result<IntCounter> = try_create_int_counter(
+        "mothra_discovery_peers_found_total",
+        "Number of peers returned by discovery queries"
+    );
+}
+
+/// Attempts to create an `IntCounter`, returning `Err` if the registry does not accept the counter
+/// (potentially due to naming conflict).
+pub fn try_create_int_counter(name: &str, help: &str) -> Result<IntCounter> {
+    let opts = Opts::new(name, help);
+    let counter = IntCounter::with_opts(opts)?;
+    prometheus::register(Box::new(counter.clone()))?;
+    Ok(counter)
+}
+
+/// Attempts to create an `IntCounterVec`, returning `Err` if the registry does not accept the
+/// counter (potentially due to naming conflict).
+pub fn try_create_int_counter_vec(
+    name: &str,
+    help: &str,
+    label_names: &[&str],
+) -> Result<IntCounterVec> {
+    let opts = Opts::new(name, help);
+    let counter_vec = IntCounterVec::new(opts, label_names)?;
+    prometheus::register(Box::new(counter_vec.clone()))?;
+    Ok(counter_vec)
+}
+
+/// Attempts to create an `IntGaugeVec`, returning `Err` if the registry does not accept the gauge
+/// (potentially due to naming conflict).
+pub fn try_create_int_gauge_vec(
+    name: &str,
+    help: &str,
+    label_names: &[&str],
+) -> Result<IntGaugeVec> {
+    let opts = Opts::new(name, help);
+    let gauge_vec = IntGaugeVec::new(opts, label_names)?;
+    prometheus::register(Box::new(gauge_vec.clone()))?;
+    Ok(gauge_vec)
+}
+
+/// Attempts to create a `HistogramVec`, returning `Err` if the registry does not accept the
+/// histogram (potentially due to naming conflict).
+pub fn try_create_histogram_vec(
+    name: &str,
+    help: &str,
+    label_names: &[&str],
+) -> Result<HistogramVec> {
+    let opts = HistogramOpts::new(name, help);
+    let histogram_vec = HistogramVec::new(opts, label_names)?;
+    prometheus::register(Box::new(histogram_vec.clone()))?;
+    Ok(histogram_vec)
+}
+
+pub fn inc_counter(counter: &Result<IntCounter>) {
+    if let Ok(counter) = counter {
+        counter.inc();
+    }
+}
+
+pub fn inc_counter_by(counter: &Result<IntCounter>, value: i64) {
+    if let Ok(counter) = counter {
+        counter.inc_by(value);
+    }
+}
+
+pub fn inc_counter_vec(counter_vec: &Result<IntCounterVec>, labels: &[&str]) {
+    if let Ok(counter_vec) = counter_vec {
+        if let Ok(counter) = counter_vec.get_metric_with_label_values(labels) {
+            counter.inc();
+        }
+    }
+}
+
+pub fn observe_duration_vec(histogram_vec: &Result<HistogramVec>, labels: &[&str], d: Duration) {
+    if let Ok(histogram_vec) = histogram_vec {
+        if let Ok(histogram) = histogram_vec.get_metric_with_label_values(labels) {
+            histogram.observe(d.as_secs_f64());
+        }
+    }
+}
+
+/// Updates the connected peers gauge from the `PeerDB`.
+pub fn update_peer_metrics(network_globals: &NetworkGlobals) {
+    let gauge_vec = match PEERS_CONNECTED.as_ref() {
+        Ok(gauge_vec) => gauge_vec,
+        Err(_) => return,
+    };
+
+    let mut peers_per_label: HashMap<(&'static str, String), i64> = HashMap::new();
+    for (_, info) in network_globals.peers.read().connected_peers() {
+        let direction = match info.connection_status {
+            Connected { n_out, .. } if n_out > 0 => "outbound",
+            _ => "inbound",
+        };
+        let client = match &info.client.kind {
+            ClientKind::Known(kind) => kind.clone(),
+            ClientKind::Unknown => "unknown".into(),
+        };
+        *peers_per_label.entry((direction, client)).or_default() += 1;
+    }
+
+    // clients that disconnected must not keep reporting their last value
+    gauge_vec.reset();
+    for ((direction, client), count) in peers_per_label {
+        if let Ok(gauge) = gauge_vec.get_metric_with_label_values(&[direction, &client]) {
+            gauge.set(count);
+        }
+    }
+}
+
+/// Refreshes the gauges and encodes all metrics in the prometheus text format.
+pub fn gather(network_globals: &NetworkGlobals) -> std::result::Result<Vec<u8>, String> {
+    update_peer_metrics(network_globals);
+
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .map_err(|e| format!("Unable to encode metrics: {:?}", e))?;
+    Ok(buffer)
+}
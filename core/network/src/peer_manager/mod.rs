@@ -1,18 +1,25 @@
 //! Implementation of a peer management system.
 
+pub use self::peer_filter::{
+    multiaddr_ip, ConnectionDirection, ConnectionGate, FilterEntry, FilterList, IpCidr, PeerFilter,
+};
 pub use self::peerdb::*;
+use self::score::PeerScore;
+pub use self::score::{PeerScoreParams, PeerScoreThresholds, TopicScoreParams};
 use crate::discovery::{Discovery, DiscoveryEvent};
 use crate::error;
-use crate::rpc::{MetaData, Protocol, RPCError, RPCResponseErrorCode};
+use crate::rpc::{GoodbyeReason, MetaData, Protocol, RPCError, RPCResponseErrorCode};
 use crate::types::SubnetId;
 use crate::{Enr, EnrExt, HashSetDelay, NetworkConfig, NetworkGlobals, PeerId};
 use futures::prelude::*;
 use futures::Stream;
 use libp2p::core::multiaddr::Protocol as MProtocol;
+use libp2p::gossipsub::TopicHash;
 use libp2p::identify::IdentifyInfo;
 use slog::{crit, debug, error};
 use smallvec::SmallVec;
 use std::{
+    collections::HashMap,
     net::SocketAddr,
     pin::Pin,
     sync::Arc,
@@ -23,19 +30,27 @@ use std::{
 pub use libp2p::core::{identity::Keypair, Multiaddr};
 
 pub mod client;
+mod peer_filter;
 mod peer_info;
 mod peerdb;
+mod score;
 
 pub use peer_info::{PeerConnectionStatus::*, PeerInfo};
 /// The minimum reputation before a peer is disconnected.
 // Most likely this needs tweaking.
-const _MIN_REP_BEFORE_BAN: Rep = 10;
+const MIN_REP_BEFORE_BAN: Rep = 10;
+/// The time in seconds a peer whose reputation fell below `MIN_REP_BEFORE_BAN` is banned for.
+const BAN_DURATION: u64 = 3600;
 /// The time in seconds between re-status's peers.
 const STATUS_INTERVAL: u64 = 300;
 /// The time in seconds between PING events. We do not send a ping if the other peer as PING'd us within
 /// this time frame (Seconds)
 const PING_INTERVAL: u64 = 30;
 
+/// The reputation lost by an inbound peer that connects from an IP address or network that already
+/// has too many connected peers.
+const COLOCATION_PENALTY: Rep = 30;
+
 /// The heartbeat performs regular updates such as updating reputations and performing discovery
 /// requests. This defines the interval in seconds.  
 const HEARTBEAT_INTERVAL: u64 = 30;
@@ -52,8 +67,20 @@ pub struct PeerManager {
     status_peers: HashSetDelay<PeerId>,
     /// The target number of peers we would like to connect to.
     target_peers: usize,
+    /// The maximum number of inbound peers connected from the same IP address.
+    max_peers_per_ip: usize,
+    /// The maximum number of inbound peers connected from the same /24 (IPv4) or /64 (IPv6)
+    /// network.
+    max_peers_per_subnet: usize,
     /// The discovery service.
     discovery: Discovery,
+    /// The gossip scores of the peers, `None` if peer scoring is disabled.
+    peer_score: Option<PeerScore>,
+    /// The number of colocated peers of each connected peer, counted at every heartbeat rather
+    /// than for every message.
+    colocated_peers: HashMap<PeerId, usize>,
+    /// The gossip scores below which peers are restricted.
+    score_thresholds: PeerScoreThresholds,
     /// The heartbeat interval to perform routine maintenance.
     heartbeat: tokio::time::Interval,
     /// The logger associated with the `PeerManager`.
@@ -110,8 +137,10 @@ pub enum PeerManagerEvent {
     Ping(PeerId),
     /// Request METADATA from a peer.
     MetaData(PeerId),
-    /// The peer should be disconnected.
-    DisconnectPeer(PeerId),
+    /// The peer should be disconnected with the given reason.
+    DisconnectPeer(PeerId, GoodbyeReason),
+    /// The peer should be disconnected and banned for the given duration.
+    BanPeer(PeerId, Duration),
 }
 
 impl PeerManager {
@@ -130,13 +159,24 @@ impl PeerManager {
 
         let heartbeat = tokio::time::interval(tokio::time::Duration::from_secs(HEARTBEAT_INTERVAL));
 
+        let peer_score = if config.disable_peer_scoring {
+            None
+        } else {
+            Some(PeerScore::new(config.peer_score_params.clone()))
+        };
+
         Ok(PeerManager {
             network_globals,
             events: SmallVec::new(),
             ping_peers: HashSetDelay::new(Duration::from_secs(PING_INTERVAL)),
             status_peers: HashSetDelay::new(Duration::from_secs(STATUS_INTERVAL)),
             target_peers: config.max_peers, //TODO: Add support for target peers and max peers
+            max_peers_per_ip: config.max_peers_per_ip,
+            max_peers_per_subnet: config.max_peers_per_subnet,
             discovery,
+            peer_score,
+            colocated_peers: HashMap::new(),
+            score_thresholds: config.peer_score_thresholds.clone(),
             heartbeat,
             log: log.clone(),
         })
@@ -180,27 +220,92 @@ impl PeerManager {
     pub fn notify_disconnect(&mut self, peer_id: &PeerId) {
         //self.update_reputations();
         self.network_globals.peers.write().disconnect(peer_id);
+        if let Some(peer_score) = self.peer_score.as_mut() {
+            peer_score.remove_peer(peer_id);
+        }
+
+        // remove the ping and status timer for the peer
+        self.ping_peers.remove(peer_id);
+        self.status_peers.remove(peer_id);
+    }
+
+    /// Requests a dial to a peer, as long as it is not already connected, being dialed or
+    /// banned.
+    pub fn dial_peer(&mut self, peer_id: &PeerId) {
+        let peerdb = self.network_globals.peers.read();
+        if peerdb.is_connected_or_dialing(peer_id) || peerdb.peer_banned(peer_id) {
+            debug!(self.log, "Not dialing peer"; "peer_id" => peer_id.to_string(), "status" => format!("{:?}", peerdb.connection_status(peer_id)));
+            return;
+        }
+        drop(peerdb);
+        self.events.push(PeerManagerEvent::Dial(peer_id.clone()));
+    }
+
+    /// Sends a Goodbye with the given reason to a peer and disconnects it.
+    pub fn disconnect_peer(&mut self, peer_id: &PeerId, reason: GoodbyeReason) {
+        debug!(self.log, "Disconnecting peer"; "peer_id" => peer_id.to_string(), "reason" => reason.to_string());
+        self.events
+            .push(PeerManagerEvent::DisconnectPeer(peer_id.clone(), reason));
+    }
+
+    /// Updates the state of the peer as banned.
+    pub fn notify_banned(&mut self, peer_id: &PeerId) {
+        self.network_globals.peers.write().ban(peer_id);
 
         // remove the ping and status timer for the peer
         self.ping_peers.remove(peer_id);
         self.status_peers.remove(peer_id);
     }
 
+    /// Updates the state of the peer as no longer banned. The peer gets the default reputation
+    /// back, so that it is not banned again as soon as it connects.
+    pub fn notify_unbanned(&mut self, peer_id: &PeerId) {
+        let mut peerdb = self.network_globals.peers.write();
+        if peerdb.peer_banned(peer_id) {
+            peerdb.unban(peer_id);
+            peerdb.set_reputation(peer_id, DEFAULT_REPUTATION);
+        }
+    }
+
     /// Sets a peer as connected as long as their reputation allows it
     /// Informs if the peer was accepted
-    pub fn connect_ingoing(&mut self, peer_id: &PeerId) -> bool {
-        self.connect_peer(peer_id, ConnectingType::IngoingConnected)
+    pub fn connect_ingoing(&mut self, peer_id: &PeerId, address: Multiaddr) -> bool {
+        self.connect_peer(peer_id, ConnectingType::IngoingConnected, Some(address))
     }
 
     /// Sets a peer as connected as long as their reputation allows it
     /// Informs if the peer was accepted
-    pub fn connect_outgoing(&mut self, peer_id: &PeerId) -> bool {
-        self.connect_peer(peer_id, ConnectingType::OutgoingConnected)
+    pub fn connect_outgoing(&mut self, peer_id: &PeerId, address: Multiaddr) -> bool {
+        self.connect_peer(peer_id, ConnectingType::OutgoingConnected, Some(address))
     }
 
     /// Updates the database informing that a peer is being dialed.
     pub fn dialing_peer(&mut self, peer_id: &PeerId) -> bool {
-        self.connect_peer(peer_id, ConnectingType::Dialing)
+        self.connect_peer(peer_id, ConnectingType::Dialing, None)
+    }
+
+    /// Disconnects all connected peers that are no longer permitted by the allow and deny lists.
+    ///
+    /// This is called after the lists have been modified at runtime.
+    pub fn apply_peer_filter(&mut self) {
+        let rejected_peers = {
+            let peer_filter = self.network_globals.peer_filter.read();
+            self.network_globals
+                .peers
+                .read()
+                .connected_peers()
+                .filter(|(peer_id, info)| {
+                    let ip = info.seen_address.as_ref().and_then(multiaddr_ip);
+                    !peer_filter.is_allowed(peer_id, ip.as_ref())
+                })
+                .map(|(peer_id, _)| peer_id.clone())
+                .collect::<Vec<_>>()
+        };
+
+        for peer_id in rejected_peers {
+            debug!(self.log, "Peer no longer permitted by the peer filter"; "peer_id" => peer_id.to_string());
+            self.disconnect_peer(&peer_id, GoodbyeReason::Unknown);
+        }
     }
 
     /// Updates the database informing that a peer is being disconnected.
@@ -289,6 +394,75 @@ impl PeerManager {
         self.report_peer(peer_id, peer_action);
     }
 
+    /* Gossip scoring */
+
+    /// A peer subscribed to a topic.
+    pub fn peer_subscribed(&mut self, peer_id: &PeerId, topic: TopicHash) {
+        if let Some(peer_score) = self.peer_score.as_mut() {
+            peer_score.subscribed(peer_id, topic);
+        }
+    }
+
+    /// A peer unsubscribed from a topic.
+    pub fn peer_unsubscribed(&mut self, peer_id: &PeerId, topic: TopicHash) {
+        if let Some(peer_score) = self.peer_score.as_mut() {
+            peer_score.unsubscribed(peer_id, topic);
+        }
+    }
+
+    /// A peer was the first to deliver a gossipsub message.
+    ///
+    /// Returns false if the peer is graylisted and the message must be ignored. Graylisted peers
+    /// that keep sending messages get a behaviour penalty.
+    pub fn gossip_message(&mut self, peer_id: &PeerId, topics: &[TopicHash]) -> bool {
+        if self.below_threshold(peer_id, self.score_thresholds.graylist_threshold) {
+            if let Some(peer_score) = self.peer_score.as_mut() {
+                peer_score.add_penalty(peer_id);
+            }
+            return false;
+        }
+        if let Some(peer_score) = self.peer_score.as_mut() {
+            peer_score.first_delivery(peer_id, topics);
+        }
+        true
+    }
+
+    /// A gossipsub message delivered by a peer was rejected by the application.
+    pub fn invalid_gossip_message(&mut self, peer_id: &PeerId, topics: &[TopicHash]) {
+        if let Some(peer_score) = self.peer_score.as_mut() {
+            peer_score.invalid_delivery(peer_id, topics);
+        }
+    }
+
+    /// A gossipsub message delivered by a peer was older than the time to live of its topic.
+    pub fn stale_gossip_message(&mut self, peer_id: &PeerId, topics: &[TopicHash]) {
+        if let Some(peer_score) = self.peer_score.as_mut() {
+            peer_score.stale_delivery(peer_id, topics);
+        }
+    }
+
+    /// Returns true if the messages of the peer may be propagated.
+    pub fn can_propagate_from(&self, peer_id: &PeerId) -> bool {
+        !self.below_threshold(peer_id, self.score_thresholds.publish_threshold)
+    }
+
+    /// The current gossip score of a peer, `None` if peer scoring is disabled.
+    pub fn gossip_score(&self, peer_id: &PeerId) -> Option<f64> {
+        let peer_score = self.peer_score.as_ref()?;
+        // peers connected since the last heartbeat count as not colocated
+        let colocated_peers = self
+            .colocated_peers
+            .get(peer_id)
+            .copied()
+            .unwrap_or_default();
+        Some(peer_score.score(peer_id, colocated_peers))
+    }
+
+    fn below_threshold(&self, peer_id: &PeerId, threshold: f64) -> bool {
+        self.gossip_score(peer_id)
+            .map_or(false, |score| score < threshold)
+    }
+
     /// A ping request has been received.
     // NOTE: The behaviour responds with a PONG automatically
     // TODO: Update last seen
@@ -441,14 +615,61 @@ impl PeerManager {
         }
     }
 
+    /// Returns true if the peer connects from an IP address or network that already has the
+    /// maximum number of connected peers. Loopback addresses and peers that are explicitly in the
+    /// allow list are exempt.
+    fn is_colocated(&self, peerdb: &PeerDB, peer_id: &PeerId) -> bool {
+        let ip = match peerdb.seen_ip(peer_id) {
+            Some(ip) => ip,
+            None => return false,
+        };
+        if ip.is_loopback()
+            || self
+                .network_globals
+                .peer_filter
+                .read()
+                .is_explicitly_allowed(peer_id, Some(&ip))
+        {
+            return false;
+        }
+
+        (self.max_peers_per_ip > 0 && peerdb.peers_on_same_ip(peer_id) >= self.max_peers_per_ip)
+            || (self.max_peers_per_subnet > 0
+                && peerdb.peers_on_same_subnet(peer_id) >= self.max_peers_per_subnet)
+    }
+
+    /// The number of other peers connected from the same IP address, as counted by the IP
+    /// colocation factor of the gossip score. Exempt peers count as not colocated.
+    fn count_colocated_peers(&self, peerdb: &PeerDB, peer_id: &PeerId) -> usize {
+        match peerdb.seen_ip(peer_id) {
+            Some(ip)
+                if !ip.is_loopback()
+                    && !self
+                        .network_globals
+                        .peer_filter
+                        .read()
+                        .is_explicitly_allowed(peer_id, Some(&ip)) =>
+            {
+                peerdb.peers_on_same_ip(peer_id)
+            }
+            _ => 0,
+        }
+    }
+
     /// Registers a peer as connected. The `ingoing` parameter determines if the peer is being
     /// dialed or connecting to us.
     ///
     /// This is called by `connect_ingoing` and `connect_outgoing`.
     ///
-    /// This informs if the peer was accepted in to the db or not.
+    /// This informs if the peer was accepted in to the db or not. Inbound peers that exceed the
+    /// per-IP or per-subnet limits are penalized and disconnected.
     // TODO: Drop peers if over max_peer limit
-    fn connect_peer(&mut self, peer_id: &PeerId, connection: ConnectingType) -> bool {
+    fn connect_peer(
+        &mut self,
+        peer_id: &PeerId,
+        connection: ConnectingType,
+        address: Option<Multiaddr>,
+    ) -> bool {
         // TODO: remove after timed updates
         //self.update_reputations();
 
@@ -461,10 +682,24 @@ impl PeerManager {
                 // return false;
             }
 
-            match connection {
-                ConnectingType::Dialing => peerdb.dialing_peer(peer_id),
-                ConnectingType::IngoingConnected => peerdb.connect_outgoing(peer_id),
-                ConnectingType::OutgoingConnected => peerdb.connect_ingoing(peer_id),
+            connection.record(&mut peerdb, peer_id);
+
+            if let Some(address) = address {
+                if let Some(info) = peerdb.peer_info_mut(peer_id) {
+                    info.seen_address = Some(address);
+                }
+            }
+
+            if let ConnectingType::IngoingConnected = connection {
+                if self.is_colocated(&peerdb, peer_id) {
+                    debug!(self.log, "Too many peers connected from the same host or network";
+                        "peer_id" => peer_id.to_string(),
+                        "ip" => format!("{:?}", peerdb.seen_ip(peer_id)));
+                    peerdb.add_reputation(peer_id, RepChange::bad(COLOCATION_PENALTY));
+                    drop(peerdb);
+                    self.disconnect_peer(peer_id, GoodbyeReason::Unknown);
+                    return false;
+                }
             }
         }
 
@@ -491,10 +726,76 @@ impl PeerManager {
     // TODO: Implement when reputation is added.
     fn _update_reputations(&mut self) {}
 
+    /// Decays the gossip scores and adds their change to the reputation of the peers. Peers whose
+    /// score fell below the graylist threshold are disconnected.
+    fn update_peer_scores(&mut self) {
+        if self.peer_score.is_none() {
+            return;
+        }
+        self.colocated_peers = {
+            let peerdb = self.network_globals.peers.read();
+            peerdb
+                .connected_peer_ids()
+                .map(|peer_id| {
+                    (
+                        peer_id.clone(),
+                        self.count_colocated_peers(&peerdb, peer_id),
+                    )
+                })
+                .collect()
+        };
+
+        let mut graylisted_peers = vec![];
+        if let Some(peer_score) = self.peer_score.as_mut() {
+            peer_score.decay();
+            let mut peerdb = self.network_globals.peers.write();
+            for (peer_id, colocated_peers) in &self.colocated_peers {
+                let score = peer_score.score(peer_id, *colocated_peers);
+                let change = peer_score.apply(peer_id, score);
+                let diff = change.abs().min(Rep::max_value() as i64) as Rep;
+                if change > 0 {
+                    peerdb.add_reputation(peer_id, RepChange::good(diff));
+                } else if change < 0 {
+                    peerdb.add_reputation(peer_id, RepChange::bad(diff));
+                }
+                if score < self.score_thresholds.graylist_threshold {
+                    graylisted_peers.push((peer_id.clone(), score));
+                }
+            }
+        }
+
+        for (peer_id, score) in graylisted_peers {
+            debug!(self.log, "Peer score below the graylist threshold";
+                "peer_id" => peer_id.to_string(), "score" => score);
+            self.disconnect_peer(&peer_id, GoodbyeReason::Fault);
+        }
+    }
+
+    /// Bans the connected peers whose reputation fell below `MIN_REP_BEFORE_BAN`.
+    fn ban_bad_peers(&mut self) {
+        let bad_peers = self
+            .network_globals
+            .peers
+            .read()
+            .connected_peers()
+            .filter(|(_, info)| info.reputation < MIN_REP_BEFORE_BAN)
+            .map(|(peer_id, info)| (peer_id.clone(), info.reputation))
+            .collect::<Vec<_>>();
+
+        for (peer_id, reputation) in bad_peers {
+            debug!(self.log, "Peer reputation too low, banning";
+                "peer_id" => peer_id.to_string(), "reputation" => reputation);
+            self.events.push(PeerManagerEvent::BanPeer(
+                peer_id,
+                Duration::from_secs(BAN_DURATION),
+            ));
+        }
+    }
+
     /// The Peer manager's heartbeat maintains the peer count and maintains peer reputations.
     ///
     /// It will request discovery queries if the peer count has not reached the desired number of
-    /// peers.
+    /// peers, folds the gossip scores into the reputations and bans peers with a bad reputation.
     ///
     /// NOTE: Discovery will only add a new query if one isn't already queued.
     fn heartbeat(&mut self) {
@@ -509,7 +810,8 @@ impl PeerManager {
         // TODO: If we have too many peers, remove peers that are not required for subnet
         // validation.
 
-        // TODO: Perform peer reputation maintenance here
+        self.update_peer_scores();
+        self.ban_bad_peers();
     }
 }
 
@@ -577,3 +879,37 @@ enum ConnectingType {
     /// We have successfully dialed a peer.
     OutgoingConnected,
 }
+
+impl ConnectingType {
+    /// Updates the connection status of a peer in the database.
+    fn record(&self, peerdb: &mut PeerDB, peer_id: &PeerId) {
+        match self {
+            ConnectingType::Dialing => peerdb.dialing_peer(peer_id),
+            ConnectingType::IngoingConnected => peerdb.connect_ingoing(peer_id),
+            ConnectingType::OutgoingConnected => peerdb.connect_outgoing(peer_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_direction() {
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let mut peerdb = PeerDB::new(&log);
+        let (inbound, outbound) = (PeerId::random(), PeerId::random());
+
+        ConnectingType::IngoingConnected.record(&mut peerdb, &inbound);
+        ConnectingType::OutgoingConnected.record(&mut peerdb, &outbound);
+        assert!(matches!(
+            peerdb.connection_status(&inbound),
+            Some(Connected { n_in: 1, n_out: 0 })
+        ));
+        assert!(matches!(
+            peerdb.connection_status(&outbound),
+            Some(Connected { n_in: 0, n_out: 1 })
+        ));
+    }
+}
@@ -0,0 +1,353 @@
+//! Allow and deny lists of peer ids and IP ranges used to gate connections.
+
+use super::client::Client;
+use crate::{Multiaddr, PeerId};
+use libp2p::core::multiaddr::Protocol;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// The direction of a connection, relative to the local node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionDirection {
+    /// The remote peer dialed us.
+    Incoming,
+    /// We dialed the remote peer.
+    Outgoing,
+}
+
+/// A function deciding whether a connection with a peer is accepted, given the peer's remote
+/// address, the direction of the connection and the client information known about the peer.
+///
+/// It is called once per connection, from the tasks that drive the connections, once the peer is
+/// authenticated and before any traffic is exchanged with it. Dialed peers, including discovered
+/// ones, are not gated before the dial. It must not block.
+pub type ConnectionGate =
+    Arc<dyn Fn(&PeerId, &Multiaddr, ConnectionDirection, &Client) -> bool + Send + Sync>;
+
+/// An IP network in CIDR notation, i.e `10.0.0.0/8` or `fe80::/10`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IpCidr {
+    /// The network address, with all host bits set to zero.
+    network: IpAddr,
+    /// The number of leading bits that make up the network prefix.
+    prefix: u8,
+}
+
+impl IpCidr {
+    /// Builds a new network from an address and a prefix length. Host bits of the address are
+    /// ignored.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, String> {
+        let network = match addr {
+            IpAddr::V4(v4) => {
+                if prefix > 32 {
+                    return Err(format!("Invalid IPv4 prefix length: {}", prefix));
+                }
+                IpAddr::V4((u32::from(v4) & v4_mask(prefix)).into())
+            }
+            IpAddr::V6(v6) => {
+                if prefix > 128 {
+                    return Err(format!("Invalid IPv6 prefix length: {}", prefix));
+                }
+                IpAddr::V6((u128::from(v6) & v6_mask(prefix)).into())
+            }
+        };
+        Ok(IpCidr { network, prefix })
+    }
+
+    /// Returns true if the address is part of this network.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                u32::from(*ip) & v4_mask(self.prefix) == u32::from(network)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                u128::from(*ip) & v6_mask(self.prefix) == u128::from(network)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn v4_mask(prefix: u8) -> u32 {
+    u32::max_value()
+        .checked_shl(32 - prefix as u32)
+        .unwrap_or(0)
+}
+
+fn v6_mask(prefix: u8) -> u128 {
+    u128::max_value()
+        .checked_shl(128 - prefix as u32)
+        .unwrap_or(0)
+}
+
+impl FromStr for IpCidr {
+    type Err = String;
+
+    /// Parses `<ip>/<prefix>`. A bare IP address is treated as a single host network.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.splitn(2, '/');
+        let addr = split
+            .next()
+            .unwrap_or_default()
+            .parse::<IpAddr>()
+            .map_err(|_| format!("Invalid IP address: {}", s))?;
+        let prefix = match split.next() {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .map_err(|_| format!("Invalid prefix length: {}", s))?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        IpCidr::new(addr, prefix)
+    }
+}
+
+impl std::fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// An entry of the allow or deny list.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum FilterEntry {
+    /// Matches a single peer id.
+    Peer(PeerId),
+    /// Matches every peer connecting from an IP address within the network.
+    Network(IpCidr),
+}
+
+impl FilterEntry {
+    /// Returns true if the entry matches the peer, or the IP it is connecting from.
+    pub fn matches(&self, peer_id: &PeerId, ip: Option<&IpAddr>) -> bool {
+        match self {
+            FilterEntry::Peer(id) => id == peer_id,
+            FilterEntry::Network(network) => ip.map_or(false, |ip| network.contains(ip)),
+        }
+    }
+}
+
+impl FromStr for FilterEntry {
+    type Err = String;
+
+    /// Parses either a base58 encoded peer id or an IP network in CIDR notation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(network) = s.parse::<IpCidr>() {
+            return Ok(FilterEntry::Network(network));
+        }
+        s.parse::<PeerId>()
+            .map(FilterEntry::Peer)
+            .map_err(|_| format!("Invalid peer id or CIDR: {}", s))
+    }
+}
+
+impl TryFrom<String> for FilterEntry {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<FilterEntry> for String {
+    fn from(entry: FilterEntry) -> String {
+        entry.to_string()
+    }
+}
+
+impl std::fmt::Display for FilterEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterEntry::Peer(peer_id) => write!(f, "{}", peer_id),
+            FilterEntry::Network(network) => write!(f, "{}", network),
+        }
+    }
+}
+
+/// Selects which of the lists of a `PeerFilter` is modified.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterList {
+    /// The allow list. When it is not empty, only matching peers may connect.
+    Allow,
+    /// The deny list. Matching peers may never connect.
+    Deny,
+}
+
+/// Decides which peers we are willing to connect to, based on their peer id and IP address.
+///
+/// The deny list always takes precedence over the allow list.
+#[derive(Clone, Debug, Default)]
+pub struct PeerFilter {
+    /// Entries of peers that are allowed to connect.
+    allow: HashSet<FilterEntry>,
+    /// Entries of peers that are not allowed to connect.
+    deny: HashSet<FilterEntry>,
+}
+
+impl PeerFilter {
+    pub fn new(allow: &[FilterEntry], deny: &[FilterEntry]) -> Self {
+        PeerFilter {
+            allow: allow.iter().cloned().collect(),
+            deny: deny.iter().cloned().collect(),
+        }
+    }
+
+    /// Adds an entry to a list. Returns false if the entry was already present.
+    pub fn insert(&mut self, list: FilterList, entry: FilterEntry) -> bool {
+        self.list_mut(list).insert(entry)
+    }
+
+    /// Removes an entry from a list. Returns false if the entry was not present.
+    pub fn remove(&mut self, list: FilterList, entry: &FilterEntry) -> bool {
+        self.list_mut(list).remove(entry)
+    }
+
+    /// Returns an iterator over the entries of a list.
+    pub fn entries(&self, list: FilterList) -> impl Iterator<Item = &FilterEntry> {
+        match list {
+            FilterList::Allow => self.allow.iter(),
+            FilterList::Deny => self.deny.iter(),
+        }
+    }
+
+    /// Returns true if the peer, connecting from the given IP (if known), is allowed to connect.
+    pub fn is_allowed(&self, peer_id: &PeerId, ip: Option<&IpAddr>) -> bool {
+        if self.deny.iter().any(|entry| entry.matches(peer_id, ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|entry| entry.matches(peer_id, ip))
+    }
+
+    /// Returns true if the peer, or the IP it is connecting from, matches an entry of the allow
+    /// list and no entry of the deny list.
+    pub fn is_explicitly_allowed(&self, peer_id: &PeerId, ip: Option<&IpAddr>) -> bool {
+        !self.allow.is_empty() && self.is_allowed(peer_id, ip)
+    }
+
+    /// Returns true if an IP address may be dialed when the peer id behind it is not yet known.
+    ///
+    /// If the allow list contains peer ids, the address is permitted and the decision is left to
+    /// `is_allowed` once the connection is established.
+    pub fn is_ip_allowed(&self, ip: &IpAddr) -> bool {
+        let network_matches = |entry: &FilterEntry| match entry {
+            FilterEntry::Network(network) => network.contains(ip),
+            FilterEntry::Peer(_) => false,
+        };
+        if self.deny.iter().any(network_matches) {
+            return false;
+        }
+        self.allow.is_empty()
+            || self.allow.iter().any(|entry| match entry {
+                FilterEntry::Peer(_) => true,
+                FilterEntry::Network(_) => network_matches(entry),
+            })
+    }
+
+    fn list_mut(&mut self, list: FilterList) -> &mut HashSet<FilterEntry> {
+        match list {
+            FilterList::Allow => &mut self.allow,
+            FilterList::Deny => &mut self.deny,
+        }
+    }
+}
+
+/// Returns the first IP address of a multiaddr, if any.
+pub fn multiaddr_ip(address: &Multiaddr) -> Option<IpAddr> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cidr_contains() {
+        let network: IpCidr = "192.168.1.0/24".parse().unwrap();
+        assert!(network.contains(&"192.168.1.1".parse().unwrap()));
+        assert!(network.contains(&"192.168.1.255".parse().unwrap()));
+        assert!(!network.contains(&"192.168.2.1".parse().unwrap()));
+        assert!(!network.contains(&"::1".parse().unwrap()));
+
+        let any: IpCidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(&"8.8.8.8".parse().unwrap()));
+
+        let host: IpCidr = "10.0.0.1".parse().unwrap();
+        assert!(host.contains(&"10.0.0.1".parse().unwrap()));
+        assert!(!host.contains(&"10.0.0.2".parse().unwrap()));
+
+        let v6: IpCidr = "fe80::/10".parse().unwrap();
+        assert!(v6.contains(&"fe80::1".parse().unwrap()));
+        assert!(!v6.contains(&"2001:db8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_cidr_parse() {
+        assert!("10.0.0.0/33".parse::<IpCidr>().is_err());
+        assert!("10.0.0/8".parse::<IpCidr>().is_err());
+        assert!("::/129".parse::<IpCidr>().is_err());
+        // host bits are dropped
+        let network: IpCidr = "10.1.2.3/8".parse().unwrap();
+        assert_eq!(network.to_string(), "10.0.0.0/8");
+    }
+
+    #[test]
+    fn test_filter_entry_parse() {
+        let peer_id = PeerId::random();
+        assert_eq!(
+            peer_id.to_string().parse::<FilterEntry>(),
+            Ok(FilterEntry::Peer(peer_id))
+        );
+        assert!(matches!(
+            "127.0.0.0/8".parse::<FilterEntry>(),
+            Ok(FilterEntry::Network(_))
+        ));
+        assert!("not a peer".parse::<FilterEntry>().is_err());
+    }
+
+    #[test]
+    fn test_deny_takes_precedence() {
+        let peer_id = PeerId::random();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let mut filter = PeerFilter::default();
+        assert!(filter.is_allowed(&peer_id, Some(&ip)));
+
+        filter.insert(FilterList::Allow, FilterEntry::Peer(peer_id.clone()));
+        assert!(filter.is_allowed(&peer_id, Some(&ip)));
+        // the allow list is not empty, other peers are rejected
+        assert!(!filter.is_allowed(&PeerId::random(), Some(&ip)));
+
+        filter.insert(FilterList::Deny, "10.0.0.0/8".parse().unwrap());
+        assert!(!filter.is_allowed(&peer_id, Some(&ip)));
+        assert!(!filter.is_ip_allowed(&ip));
+
+        filter.remove(FilterList::Deny, &"10.0.0.0/8".parse().unwrap());
+        assert!(filter.is_allowed(&peer_id, Some(&ip)));
+        assert!(filter.is_explicitly_allowed(&peer_id, Some(&ip)));
+
+        // with an empty allow list peers are permitted, but not explicitly
+        filter.remove(FilterList::Allow, &FilterEntry::Peer(peer_id.clone()));
+        assert!(filter.is_allowed(&peer_id, Some(&ip)));
+        assert!(!filter.is_explicitly_allowed(&peer_id, Some(&ip)));
+    }
+
+    #[test]
+    fn test_ip_allowed() {
+        let mut filter = PeerFilter::default();
+        filter.insert(FilterList::Allow, "10.0.0.0/8".parse().unwrap());
+        assert!(filter.is_ip_allowed(&"10.1.1.1".parse().unwrap()));
+        assert!(!filter.is_ip_allowed(&"11.1.1.1".parse().unwrap()));
+
+        // a peer id in the allow list defers the decision until the peer id is known
+        filter.insert(FilterList::Allow, FilterEntry::Peer(PeerId::random()));
+        assert!(filter.is_ip_allowed(&"11.1.1.1".parse().unwrap()));
+    }
+}
@@ -23,6 +23,8 @@ pub struct PeerInfo {
     pub connection_status: PeerConnectionStatus,
     /// The known listening addresses of this peer.
     pub listening_addresses: Vec<Multiaddr>,
+    /// The address the current connection with this peer was established on.
+    pub seen_address: Option<Multiaddr>,
     /// The ENR subnet bitfield of the peer. This may be determined after it's initial
     /// connection.
     pub meta_data: Option<MetaData>,
@@ -40,6 +42,7 @@ impl Default for PeerInfo {
             client: Client::default(),
             connection_status: Default::default(),
             listening_addresses: vec![],
+            seen_address: None,
             meta_data: None,
             min_ttl: None,
         }
@@ -1,9 +1,11 @@
+use super::peer_filter::{multiaddr_ip, IpCidr};
 use super::peer_info::{PeerConnectionStatus, PeerInfo};
 use crate::rpc::methods::MetaData;
 use crate::types::SubnetId;
 use crate::PeerId;
 use slog::{crit, debug, trace, warn};
 use std::collections::{hash_map::Entry, HashMap};
+use std::net::IpAddr;
 use std::time::Instant;
 
 /// A peer's reputation (perceived potential usefulness)
@@ -21,6 +23,12 @@ const MAX_DC_PEERS: usize = 30;
 /// The default starting reputation for an unknown peer.
 pub const DEFAULT_REPUTATION: Rep = 50;
 
+/// The prefix length of the IPv4 networks used to group colocated peers.
+const IPV4_SUBNET_PREFIX: u8 = 24;
+
+/// The prefix length of the IPv6 networks used to group colocated peers.
+const IPV6_SUBNET_PREFIX: u8 = 64;
+
 /// Storage of known peers, their reputation and information
 pub struct PeerDB {
     /// The collection of known connected peers, their status and reputation
@@ -119,6 +127,44 @@ impl PeerDB {
             .map(|(peer_id, _)| peer_id)
     }
 
+    /// Returns the IP address the connection with a peer was established on, if known.
+    pub fn seen_ip(&self, peer_id: &PeerId) -> Option<IpAddr> {
+        self.peers
+            .get(peer_id)
+            .and_then(|info| info.seen_address.as_ref())
+            .and_then(multiaddr_ip)
+    }
+
+    /// Gives the number of other connected peers whose connection was established from the same
+    /// IP address as the given peer.
+    pub fn peers_on_same_ip(&self, peer_id: &PeerId) -> usize {
+        match self.seen_ip(peer_id) {
+            Some(ip) => self.count_colocated(peer_id, |other| *other == ip),
+            None => 0,
+        }
+    }
+
+    /// Gives the number of other connected peers whose connection was established from the same
+    /// /24 (IPv4) or /64 (IPv6) network as the given peer.
+    pub fn peers_on_same_subnet(&self, peer_id: &PeerId) -> usize {
+        let network = match self.seen_ip(peer_id) {
+            Some(ip @ IpAddr::V4(_)) => IpCidr::new(ip, IPV4_SUBNET_PREFIX),
+            Some(ip @ IpAddr::V6(_)) => IpCidr::new(ip, IPV6_SUBNET_PREFIX),
+            None => return 0,
+        }
+        .expect("prefix lengths are valid");
+        self.count_colocated(peer_id, |other| network.contains(other))
+    }
+
+    /// Counts the connected peers, other than `peer_id`, whose seen IP matches `predicate`.
+    fn count_colocated(&self, peer_id: &PeerId, predicate: impl Fn(&IpAddr) -> bool) -> usize {
+        self.connected_peers()
+            .filter(|(id, _)| *id != peer_id)
+            .filter_map(|(_, info)| info.seen_address.as_ref().and_then(multiaddr_ip))
+            .filter(|ip| predicate(ip))
+            .count()
+    }
+
     /// Connected or dialing peers
     pub fn connected_or_dialing_peers(&self) -> impl Iterator<Item = &PeerId> {
         self.peers
@@ -288,6 +334,7 @@ impl PeerDB {
             info.connection_status.disconnect();
             self.n_dc += 1;
         }
+        info.seen_address = None;
         self.shrink_to_fit();
     }
 
@@ -322,6 +369,20 @@ impl PeerDB {
         info.connection_status.ban();
     }
 
+    /// Lifts the ban of a peer, marking it as disconnected. Peers that are not banned are left
+    /// unchanged.
+    pub fn unban(&mut self, peer_id: &PeerId) {
+        if let Some(info) = self.peers.get_mut(peer_id) {
+            if info.connection_status.is_banned() {
+                info.connection_status.disconnect();
+                self.n_dc += 1;
+                self.shrink_to_fit();
+            }
+        } else {
+            debug!(self.log, "Unbanning unknown peer"; "peer_id" => peer_id.to_string());
+        }
+    }
+
     /// Add the meta data of a peer.
     pub fn add_metadata(&mut self, peer_id: &PeerId, meta_data: MetaData) {
         if let Some(peer_info) = self.peers.get_mut(peer_id) {
@@ -332,7 +393,6 @@ impl PeerDB {
     }
 
     /// Sets the reputation of peer.
-    #[allow(dead_code)]
     pub(super) fn set_reputation(&mut self, peer_id: &PeerId, rep: Rep) {
         if let Some(peer_info) = self.peers.get_mut(peer_id) {
             peer_info.reputation = rep;
@@ -384,6 +444,11 @@ mod tests {
         PeerDB::new(&log)
     }
 
+    fn connect_from(pdb: &mut PeerDB, peer_id: &PeerId, address: &str) {
+        pdb.connect_ingoing(peer_id);
+        pdb.peer_info_mut(peer_id).unwrap().seen_address = Some(address.parse().unwrap());
+    }
+
     #[test]
     fn test_peer_connected_successfully() {
         let mut pdb = get_db();
@@ -543,5 +608,57 @@ mod tests {
         assert_eq!(pdb.n_dc, pdb.disconnected_peers().count());
         pdb.disconnect(&random_peer);
         assert_eq!(pdb.n_dc, pdb.disconnected_peers().count());
+
+        pdb.unban(&random_peer);
+        assert_eq!(pdb.n_dc, pdb.disconnected_peers().count());
+        pdb.unban(&random_peer);
+        assert_eq!(pdb.n_dc, pdb.disconnected_peers().count());
+    }
+
+    #[test]
+    fn test_unban() {
+        let mut pdb = get_db();
+        let random_peer = PeerId::random();
+
+        pdb.connect_ingoing(&random_peer);
+        pdb.ban(&random_peer);
+        assert!(pdb.peer_banned(&random_peer));
+
+        pdb.unban(&random_peer);
+        assert!(!pdb.peer_banned(&random_peer));
+        assert!(pdb
+            .connection_status(&random_peer)
+            .unwrap()
+            .is_disconnected());
+    }
+
+    #[test]
+    fn test_colocated_peers() {
+        let mut pdb = get_db();
+        let p0 = PeerId::random();
+        let p1 = PeerId::random();
+        let p2 = PeerId::random();
+        let p3 = PeerId::random();
+        let p4 = PeerId::random();
+
+        connect_from(&mut pdb, &p0, "/ip4/10.0.0.1/tcp/9000");
+        connect_from(&mut pdb, &p1, "/ip4/10.0.0.1/tcp/9001");
+        connect_from(&mut pdb, &p2, "/ip4/10.0.0.2/tcp/9000");
+        connect_from(&mut pdb, &p3, "/ip4/10.0.1.1/tcp/9000");
+        connect_from(&mut pdb, &p4, "/ip6/fe80::1/tcp/9000");
+
+        assert_eq!(pdb.peers_on_same_ip(&p0), 1);
+        assert_eq!(pdb.peers_on_same_subnet(&p0), 2);
+        assert_eq!(pdb.peers_on_same_ip(&p2), 0);
+        assert_eq!(pdb.peers_on_same_subnet(&p3), 0);
+        assert_eq!(pdb.peers_on_same_subnet(&p4), 0);
+
+        // disconnected peers are not counted
+        pdb.disconnect(&p1);
+        assert_eq!(pdb.peers_on_same_ip(&p0), 0);
+        assert_eq!(pdb.peers_on_same_subnet(&p0), 1);
+
+        // unknown peers have no colocated peers
+        assert_eq!(pdb.peers_on_same_ip(&PeerId::random()), 0);
     }
 }
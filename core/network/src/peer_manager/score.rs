@@ -0,0 +1,513 @@
+//! Gossipsub v1.1 style peer scoring.
+//!
+//! Gossipsub does not score peers nor expose its mesh (see `Behaviour`), so scores are computed
+//! here from the events it reports, and the time a connected peer has been subscribed to a topic
+//! stands in for its time in the mesh.
+//!
+//! Counters decay at every heartbeat of the `PeerManager`.
+
+use crate::PeerId;
+use libp2p::gossipsub::TopicHash;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Counters that decay below this value are reset to zero.
+const DECAY_TO_ZERO: f64 = 0.01;
+
+/// Scoring parameters of a single topic.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicScoreParams {
+    /// The weight of the topic score in the peer score.
+    pub topic_weight: f64,
+    /// The weight of the time in mesh (P1). Must not be negative.
+    pub time_in_mesh_weight: f64,
+    /// The time in milliseconds counted as one unit of time in mesh.
+    pub time_in_mesh_quantum_ms: u64,
+    /// The maximum number of time in mesh units counted.
+    pub time_in_mesh_cap: f64,
+    /// The weight of the first message deliveries (P2). Must not be negative.
+    pub first_message_deliveries_weight: f64,
+    /// The factor first message deliveries are multiplied by at every heartbeat.
+    pub first_message_deliveries_decay: f64,
+    /// The maximum number of first message deliveries counted.
+    pub first_message_deliveries_cap: f64,
+    /// The weight of the square of the invalid message deliveries (P4). Must not be positive.
+    pub invalid_message_deliveries_weight: f64,
+    /// The factor invalid message deliveries are multiplied by at every heartbeat.
+    pub invalid_message_deliveries_decay: f64,
+    /// The weight of the deliveries of messages older than the time to live of the topic. Must not
+    /// be positive.
+    pub stale_message_deliveries_weight: f64,
+    /// The factor stale message deliveries are multiplied by at every heartbeat.
+    pub stale_message_deliveries_decay: f64,
+}
+
+impl Default for TopicScoreParams {
+    fn default() -> Self {
+        TopicScoreParams {
+            topic_weight: 1.0,
+            time_in_mesh_weight: 0.02,
+            time_in_mesh_quantum_ms: 10_000,
+            time_in_mesh_cap: 300.0,
+            first_message_deliveries_weight: 1.0,
+            first_message_deliveries_decay: 0.5,
+            first_message_deliveries_cap: 10.0,
+            invalid_message_deliveries_weight: -10.0,
+            invalid_message_deliveries_decay: 0.5,
+            stale_message_deliveries_weight: -1.0,
+            stale_message_deliveries_decay: 0.5,
+        }
+    }
+}
+
+impl TopicScoreParams {
+    fn validate(&self, topic: &str) -> Result<(), String> {
+        if self.topic_weight < 0.0 {
+            return Err(format!(
+                "The weight of topic {} must not be negative",
+                topic
+            ));
+        }
+        if self.time_in_mesh_weight < 0.0 || self.first_message_deliveries_weight < 0.0 {
+            return Err(format!(
+                "The time in mesh and first delivery weights of topic {} must not be negative",
+                topic
+            ));
+        }
+        if self.invalid_message_deliveries_weight > 0.0
+            || self.stale_message_deliveries_weight > 0.0
+        {
+            return Err(format!(
+                "The invalid and stale delivery weights of topic {} must not be positive",
+                topic
+            ));
+        }
+        if self.time_in_mesh_quantum_ms == 0 {
+            return Err(format!(
+                "The time in mesh quantum of topic {} must be positive",
+                topic
+            ));
+        }
+        if !valid_decay(self.first_message_deliveries_decay)
+            || !valid_decay(self.invalid_message_deliveries_decay)
+            || !valid_decay(self.stale_message_deliveries_decay)
+        {
+            return Err(format!(
+                "The decays of topic {} must be between 0 and 1",
+                topic
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Peer scoring parameters.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeerScoreParams {
+    /// Scoring parameters keyed by topic. Topics without an entry use `default_topic`.
+    pub topics: HashMap<String, TopicScoreParams>,
+    /// Scoring parameters of the topics without an entry in `topics`.
+    pub default_topic: TopicScoreParams,
+    /// The maximum score all topics together contribute. 0 disables the cap.
+    pub topic_score_cap: f64,
+    /// The weight of the square of the peers connected from the same IP address above the
+    /// threshold (P6). Must not be positive.
+    pub ip_colocation_factor_weight: f64,
+    /// The number of peers connected from the same IP address that are not penalized.
+    pub ip_colocation_factor_threshold: usize,
+    /// The weight of the square of the behaviour penalty (P7). Must not be positive.
+    pub behaviour_penalty_weight: f64,
+    /// The factor the behaviour penalty is multiplied by at every heartbeat.
+    pub behaviour_penalty_decay: f64,
+}
+
+impl Default for PeerScoreParams {
+    fn default() -> Self {
+        PeerScoreParams {
+            topics: HashMap::new(),
+            default_topic: TopicScoreParams::default(),
+            topic_score_cap: 20.0,
+            ip_colocation_factor_weight: -5.0,
+            ip_colocation_factor_threshold: 3,
+            behaviour_penalty_weight: -1.0,
+            behaviour_penalty_decay: 0.9,
+        }
+    }
+}
+
+impl PeerScoreParams {
+    /// Checks that the weights have the right sign and that the decays are valid.
+    pub fn validate(&self) -> Result<(), String> {
+        self.default_topic.validate("default")?;
+        for (topic, params) in &self.topics {
+            params.validate(topic)?;
+        }
+        if self.topic_score_cap < 0.0 {
+            return Err("The topic score cap must not be negative".into());
+        }
+        if self.ip_colocation_factor_weight > 0.0 || self.behaviour_penalty_weight > 0.0 {
+            return Err(
+                "The IP colocation and behaviour penalty weights must not be positive".into(),
+            );
+        }
+        if self.ip_colocation_factor_threshold == 0 {
+            return Err("The IP colocation threshold must be positive".into());
+        }
+        if !valid_decay(self.behaviour_penalty_decay) {
+            return Err("The behaviour penalty decay must be between 0 and 1".into());
+        }
+        Ok(())
+    }
+
+    fn topic(&self, topic: &TopicHash) -> &TopicScoreParams {
+        self.topics
+            .get(topic.as_str())
+            .unwrap_or(&self.default_topic)
+    }
+}
+
+/// The scores below which peers are restricted.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeerScoreThresholds {
+    /// Messages received from peers below this score are not propagated.
+    // The gossipsub implementation chooses the peers our own messages are published to, so the
+    // threshold applies to the messages we would relay on their behalf.
+    pub publish_threshold: f64,
+    /// Messages received from peers below this score are ignored and the peers are disconnected.
+    pub graylist_threshold: f64,
+}
+
+impl Default for PeerScoreThresholds {
+    fn default() -> Self {
+        PeerScoreThresholds {
+            publish_threshold: -10.0,
+            graylist_threshold: -40.0,
+        }
+    }
+}
+
+impl PeerScoreThresholds {
+    /// Checks that graylist threshold <= publish threshold <= 0.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.publish_threshold > 0.0 || self.graylist_threshold > self.publish_threshold {
+            return Err(format!(
+                "Peer score thresholds must satisfy graylist <= publish <= 0, got {} <= {} <= 0",
+                self.graylist_threshold, self.publish_threshold
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The gossip scores of the connected peers.
+pub struct PeerScore {
+    params: PeerScoreParams,
+    peer_stats: HashMap<PeerId, PeerStats>,
+}
+
+#[derive(Default)]
+struct PeerStats {
+    topics: HashMap<TopicHash, TopicStats>,
+    behaviour_penalty: f64,
+    /// The part of the score that has been added to the reputation of the peer.
+    applied_score: i64,
+}
+
+#[derive(Default)]
+struct TopicStats {
+    /// Since when the peer is subscribed to the topic, if it is.
+    subscribed_since: Option<Instant>,
+    first_message_deliveries: f64,
+    invalid_message_deliveries: f64,
+    stale_message_deliveries: f64,
+}
+
+impl PeerScore {
+    pub fn new(params: PeerScoreParams) -> Self {
+        PeerScore {
+            params,
+            peer_stats: HashMap::new(),
+        }
+    }
+
+    /// Forgets a disconnected peer. The score it had is kept in its reputation.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.peer_stats.remove(peer_id);
+    }
+
+    /// The peer subscribed to a topic.
+    pub fn subscribed(&mut self, peer_id: &PeerId, topic: TopicHash) {
+        let stats = self.topic_stats(peer_id, topic);
+        if stats.subscribed_since.is_none() {
+            stats.subscribed_since = Some(Instant::now());
+        }
+    }
+
+    /// The peer unsubscribed from a topic.
+    pub fn unsubscribed(&mut self, peer_id: &PeerId, topic: TopicHash) {
+        self.topic_stats(peer_id, topic).subscribed_since = None;
+    }
+
+    /// The peer was the first to deliver a message.
+    pub fn first_delivery(&mut self, peer_id: &PeerId, topics: &[TopicHash]) {
+        for topic in topics {
+            let cap = self.params.topic(topic).first_message_deliveries_cap;
+            let stats = self.topic_stats(peer_id, topic.clone());
+            stats.first_message_deliveries = (stats.first_message_deliveries + 1.0).min(cap);
+        }
+    }
+
+    /// The peer delivered a message that was rejected by the application.
+    pub fn invalid_delivery(&mut self, peer_id: &PeerId, topics: &[TopicHash]) {
+        for topic in topics {
+            self.topic_stats(peer_id, topic.clone())
+                .invalid_message_deliveries += 1.0;
+        }
+    }
+
+    /// The peer delivered a message older than the time to live of its topic.
+    pub fn stale_delivery(&mut self, peer_id: &PeerId, topics: &[TopicHash]) {
+        for topic in topics {
+            self.topic_stats(peer_id, topic.clone())
+                .stale_message_deliveries += 1.0;
+        }
+    }
+
+    /// The peer misbehaved at the gossipsub protocol level.
+    pub fn add_penalty(&mut self, peer_id: &PeerId) {
+        self.peer_stats
+            .entry(peer_id.clone())
+            .or_default()
+            .behaviour_penalty += 1.0;
+    }
+
+    /// Decays the counters of all peers.
+    pub fn decay(&mut self) {
+        let params = &self.params;
+        for stats in self.peer_stats.values_mut() {
+            for (topic, topic_stats) in stats.topics.iter_mut() {
+                let topic_params = params.topic(topic);
+                decay(
+                    &mut topic_stats.first_message_deliveries,
+                    topic_params.first_message_deliveries_decay,
+                );
+                decay(
+                    &mut topic_stats.invalid_message_deliveries,
+                    topic_params.invalid_message_deliveries_decay,
+                );
+                decay(
+                    &mut topic_stats.stale_message_deliveries,
+                    topic_params.stale_message_deliveries_decay,
+                );
+            }
+            decay(&mut stats.behaviour_penalty, params.behaviour_penalty_decay);
+        }
+    }
+
+    /// Computes the score of a peer. `colocated_peers` is the number of other peers connected from
+    /// the same IP address.
+    pub fn score(&self, peer_id: &PeerId, colocated_peers: usize) -> f64 {
+        self.score_at(peer_id, colocated_peers, Instant::now())
+    }
+
+    fn score_at(&self, peer_id: &PeerId, colocated_peers: usize, now: Instant) -> f64 {
+        let mut score = 0.0;
+        if let Some(stats) = self.peer_stats.get(peer_id) {
+            for (topic, topic_stats) in &stats.topics {
+                let params = self.params.topic(topic);
+                let mut topic_score = 0.0;
+
+                // P1: time in mesh
+                if let Some(since) = topic_stats.subscribed_since {
+                    let in_mesh = now.saturating_duration_since(since).as_millis() as f64
+                        / params.time_in_mesh_quantum_ms as f64;
+                    topic_score +=
+                        params.time_in_mesh_weight * in_mesh.min(params.time_in_mesh_cap);
+                }
+                // P2: first message deliveries
+                topic_score +=
+                    params.first_message_deliveries_weight * topic_stats.first_message_deliveries;
+                // P4: invalid message deliveries
+                topic_score += params.invalid_message_deliveries_weight
+                    * topic_stats.invalid_message_deliveries.powi(2);
+                // stale message deliveries
+                topic_score +=
+                    params.stale_message_deliveries_weight * topic_stats.stale_message_deliveries;
+
+                score += params.topic_weight * topic_score;
+            }
+            if self.params.topic_score_cap > 0.0 {
+                score = score.min(self.params.topic_score_cap);
+            }
+
+            // P7: behaviour penalty
+            score += self.params.behaviour_penalty_weight * stats.behaviour_penalty.powi(2);
+        }
+
+        // P6: IP colocation factor
+        let peers_on_ip = colocated_peers + 1;
+        if peers_on_ip > self.params.ip_colocation_factor_threshold {
+            let surplus = (peers_on_ip - self.params.ip_colocation_factor_threshold) as f64;
+            score += self.params.ip_colocation_factor_weight * surplus.powi(2);
+        }
+
+        score
+    }
+
+    /// Records `score` as the part of the score added to the reputation of the peer and returns
+    /// the change since it was last recorded.
+    pub fn apply(&mut self, peer_id: &PeerId, score: f64) -> i64 {
+        let stats = self.peer_stats.entry(peer_id.clone()).or_default();
+        let score = score.round() as i64;
+        let change = score - stats.applied_score;
+        stats.applied_score = score;
+        change
+    }
+
+    fn topic_stats(&mut self, peer_id: &PeerId, topic: TopicHash) -> &mut TopicStats {
+        self.peer_stats
+            .entry(peer_id.clone())
+            .or_default()
+            .topics
+            .entry(topic)
+            .or_default()
+    }
+}
+
+fn valid_decay(decay: f64) -> bool {
+    decay > 0.0 && decay < 1.0
+}
+
+fn decay(counter: &mut f64, decay: f64) {
+    *counter *= decay;
+    if *counter < DECAY_TO_ZERO {
+        *counter = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn topic(name: &str) -> TopicHash {
+        TopicHash::from_raw(name)
+    }
+
+    #[test]
+    fn test_first_deliveries_are_capped() {
+        let mut peer_score = PeerScore::new(PeerScoreParams::default());
+        let peer_id = PeerId::random();
+        for _ in 0..100 {
+            peer_score.first_delivery(&peer_id, &[topic("blocks")]);
+        }
+        assert_eq!(peer_score.score(&peer_id, 0), 10.0);
+    }
+
+    #[test]
+    fn test_time_in_mesh() {
+        let mut peer_score = PeerScore::new(PeerScoreParams::default());
+        let peer_id = PeerId::random();
+        peer_score.subscribed(&peer_id, topic("blocks"));
+
+        let later = Instant::now() + Duration::from_secs(100);
+        let score = peer_score.score_at(&peer_id, 0, later);
+        assert!(score >= 0.2 && score < 0.21);
+
+        // the time in mesh is capped
+        let much_later = Instant::now() + Duration::from_secs(100_000);
+        assert_eq!(peer_score.score_at(&peer_id, 0, much_later), 6.0);
+
+        peer_score.unsubscribed(&peer_id, topic("blocks"));
+        assert_eq!(peer_score.score_at(&peer_id, 0, later), 0.0);
+    }
+
+    #[test]
+    fn test_invalid_deliveries_and_decay() {
+        let mut peer_score = PeerScore::new(PeerScoreParams::default());
+        let peer_id = PeerId::random();
+        peer_score.invalid_delivery(&peer_id, &[topic("blocks")]);
+        peer_score.invalid_delivery(&peer_id, &[topic("blocks")]);
+        assert_eq!(peer_score.score(&peer_id, 0), -40.0);
+
+        peer_score.decay();
+        assert_eq!(peer_score.score(&peer_id, 0), -10.0);
+
+        for _ in 0..10 {
+            peer_score.decay();
+        }
+        assert_eq!(peer_score.score(&peer_id, 0), 0.0);
+    }
+
+    #[test]
+    fn test_stale_deliveries() {
+        let mut peer_score = PeerScore::new(PeerScoreParams::default());
+        let peer_id = PeerId::random();
+        peer_score.stale_delivery(&peer_id, &[topic("blocks"), topic("attestations")]);
+        peer_score.stale_delivery(&peer_id, &[topic("blocks")]);
+        assert_eq!(peer_score.score(&peer_id, 0), -3.0);
+
+        peer_score.decay();
+        assert_eq!(peer_score.score(&peer_id, 0), -1.5);
+    }
+
+    #[test]
+    fn test_topic_params() {
+        let mut params = PeerScoreParams::default();
+        let mut blocks = TopicScoreParams::default();
+        blocks.topic_weight = 0.5;
+        params.topics.insert("blocks".into(), blocks);
+        let mut peer_score = PeerScore::new(params);
+
+        let peer_id = PeerId::random();
+        peer_score.first_delivery(&peer_id, &[topic("blocks"), topic("attestations")]);
+        assert_eq!(peer_score.score(&peer_id, 0), 1.5);
+    }
+
+    #[test]
+    fn test_colocation_and_penalty() {
+        let mut peer_score = PeerScore::new(PeerScoreParams::default());
+        let peer_id = PeerId::random();
+        assert_eq!(peer_score.score(&peer_id, 2), 0.0);
+        assert_eq!(peer_score.score(&peer_id, 4), -20.0);
+
+        peer_score.add_penalty(&peer_id);
+        peer_score.add_penalty(&peer_id);
+        assert_eq!(peer_score.score(&peer_id, 0), -4.0);
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut peer_score = PeerScore::new(PeerScoreParams::default());
+        let peer_id = PeerId::random();
+        assert_eq!(peer_score.apply(&peer_id, 4.4), 4);
+        assert_eq!(peer_score.apply(&peer_id, 4.4), 0);
+        assert_eq!(peer_score.apply(&peer_id, -10.0), -14);
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(PeerScoreParams::default().validate().is_ok());
+        assert!(PeerScoreThresholds::default().validate().is_ok());
+
+        let mut params = PeerScoreParams::default();
+        params.default_topic.invalid_message_deliveries_weight = 1.0;
+        assert!(params.validate().is_err());
+
+        let mut params = PeerScoreParams::default();
+        params.default_topic.stale_message_deliveries_weight = 1.0;
+        assert!(params.validate().is_err());
+
+        let mut params = PeerScoreParams::default();
+        params.behaviour_penalty_decay = 1.0;
+        assert!(params.validate().is_err());
+
+        let mut thresholds = PeerScoreThresholds::default();
+        thresholds.graylist_threshold = thresholds.publish_threshold + 1.0;
+        assert!(thresholds.validate().is_err());
+    }
+}
@@ -4,6 +4,7 @@
 use super::methods::{RPCCodedResponse, RequestId};
 use super::protocol::{Protocol, RPCError, RPCProtocol, RPCRequest};
 use super::{RPCReceived, RPCSend};
+use crate::metrics;
 use crate::rpc::protocol::{InboundFramed, OutboundFramed};
 use fnv::FnvHashMap;
 use futures::prelude::*;
@@ -150,6 +151,8 @@ struct OutboundInfo {
     remaining_chunks: Option<usize>,
     /// RequestId as given by the application that sent the request.
     req_id: RequestId,
+    /// When the outbound substream was opened, to measure the response time.
+    started: Instant,
 }
 
 pub enum InboundSubstreamState {
@@ -578,6 +581,7 @@ impl ProtocolsHandler for RPCHandler {
                         proto,
                         remaining_chunks: expected_responses,
                         req_id: id,
+                        started: Instant::now(),
                     },
                 )
                 .is_some()
@@ -1020,6 +1024,11 @@ impl ProtocolsHandler for RPCHandler {
                         // Check what type of response we got and report it accordingly
                         let id = entry.get().req_id;
                         let proto = entry.get().proto;
+                        metrics::observe_duration_vec(
+                            &metrics::RPC_RESPONSE_TIME,
+                            &[&proto.to_string()],
+                            entry.get().started.elapsed(),
+                        );
 
                         let received = match response {
                             RPCCodedResponse::Success(resp) => Ok(RPCReceived::Response(id, resp)),
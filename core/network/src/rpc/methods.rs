@@ -171,6 +171,16 @@ pub enum RPCResponseErrorCode {
     Unknown,
 }
 
+impl From<u8> for RPCResponseErrorCode {
+    fn from(code: u8) -> RPCResponseErrorCode {
+        match code {
+            1 => RPCResponseErrorCode::InvalidRequest,
+            2 => RPCResponseErrorCode::ServerError,
+            _ => RPCResponseErrorCode::Unknown,
+        }
+    }
+}
+
 impl RPCCodedResponse {
     /// Used to encode the response in the codec.
     pub fn as_u8(&self) -> Option<u8> {
@@ -4,6 +4,7 @@
 //! direct peer-to-peer communication primarily for sending/receiving chain information for
 //! syncing.
 
+use crate::metrics;
 use handler::RPCHandler;
 use libp2p::core::{connection::ConnectionId, ConnectedPoint};
 use libp2p::swarm::{
@@ -114,6 +115,10 @@ impl RPC {
     ///
     /// The peer must be connected for this to succeed.
     pub fn send_request(&mut self, peer_id: PeerId, request_id: RequestId, event: RPCRequest) {
+        metrics::inc_counter_vec(
+            &metrics::RPC_REQUESTS_SENT,
+            &[&event.protocol().to_string()],
+        );
         self.events.push(NetworkBehaviourAction::NotifyHandler {
             peer_id,
             handler: NotifyHandler::Any,
@@ -175,6 +180,21 @@ impl NetworkBehaviour for RPC {
         conn_id: ConnectionId,
         event: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
     ) {
+        match &event {
+            Ok(RPCReceived::Request(_, request)) => metrics::inc_counter_vec(
+                &metrics::RPC_REQUESTS_RECEIVED,
+                &[&request.protocol().to_string()],
+            ),
+            Err(HandlerErr::Inbound { proto, error, .. }) => metrics::inc_counter_vec(
+                &metrics::RPC_ERRORS,
+                &[&proto.to_string(), error.as_static_str(), "inbound"],
+            ),
+            Err(HandlerErr::Outbound { proto, error, .. }) => metrics::inc_counter_vec(
+                &metrics::RPC_ERRORS,
+                &[&proto.to_string(), error.as_static_str(), "outbound"],
+            ),
+            _ => {}
+        }
         // send the event to the user
         self.events
             .push(NetworkBehaviourAction::GenerateEvent(RPCMessage {
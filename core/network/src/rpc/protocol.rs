@@ -333,6 +333,24 @@ pub enum RPCError {
     HandlerRejected,
 }
 
+impl RPCError {
+    /// Short name of the error variant, used as a metrics label.
+    pub fn as_static_str(&self) -> &'static str {
+        match self {
+            RPCError::DecodeError => "decode_error",
+            RPCError::IoError(_) => "io_error",
+            RPCError::ErrorResponse(..) => "error_response",
+            RPCError::StreamTimeout => "stream_timeout",
+            RPCError::UnsupportedProtocol => "unsupported_protocol",
+            RPCError::IncompleteStream => "incomplete_stream",
+            RPCError::InvalidData => "invalid_data",
+            RPCError::InternalError(_) => "internal_error",
+            RPCError::NegotiationTimeout => "negotiation_timeout",
+            RPCError::HandlerRejected => "handler_rejected",
+        }
+    }
+}
+
 impl From<tokio::time::Elapsed> for RPCError {
     fn from(_: tokio::time::Elapsed) -> Self {
         RPCError::StreamTimeout
@@ -1,10 +1,13 @@
 use crate::behaviour::{Behaviour, BehaviourEvent, PeerRequestId, Request, Response};
 use crate::discovery::enr;
 use crate::multiaddr::Protocol;
-use crate::rpc::{RPCResponseErrorCode, RequestId};
-use crate::types::{error, GossipKind};
+use crate::peer_manager::{
+    multiaddr_ip, ConnectionDirection, ConnectionGate, FilterEntry, FilterList, PeerFilter,
+};
+use crate::rpc::{GoodbyeReason, RPCResponseErrorCode, RequestId};
+use crate::types::error;
 use crate::EnrExt;
-use crate::{Enr, EnrForkId, NetworkConfig, NetworkGlobals, TaskExecutor};
+use crate::{Enr, EnrForkId, HashSetDelay, NetworkConfig, NetworkGlobals, TaskExecutor};
 use futures::prelude::*;
 use libp2p::core::{
     identity::Keypair,
@@ -26,7 +29,6 @@ use std::io::{Error, ErrorKind};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::time::DelayQueue;
 
 pub const NETWORK_KEY_FILENAME: &str = "key";
 /// The time in milliseconds to wait before banning a peer. This allows for any Goodbye messages to be
@@ -73,10 +75,10 @@ pub struct Service {
     network_globals: Arc<NetworkGlobals>,
 
     /// A current list of peers to ban after a given timeout.
-    peers_to_ban: DelayQueue<PeerId>,
+    peers_to_ban: HashSetDelay<PeerId>,
 
     /// A list of timeouts after which peers become unbanned.
-    peer_ban_timeout: DelayQueue<PeerId>,
+    peer_ban_timeout: HashSetDelay<PeerId>,
 
     /// The libp2p logger handle.
     pub log: slog::Logger,
@@ -89,6 +91,7 @@ impl Service {
         enr_fork_id: EnrForkId,
         meta_data: Vec<u8>,
         ping_data: Vec<u8>,
+        connection_gate: Option<ConnectionGate>,
         log: &slog::Logger,
     ) -> error::Result<(Arc<NetworkGlobals>, Self)> {
         let log = log.new(o!("service"=> "libp2p"));
@@ -108,8 +111,10 @@ impl Service {
             ping_data,
             config.libp2p_port,
             config.discovery_port,
+            PeerFilter::new(&config.allow_list, &config.deny_list),
             &log,
         ));
+        *network_globals.connection_gate.write() = connection_gate;
 
         info!(log, "Libp2p Service"; "peer_id" => format!("{:?}", enr.peer_id()));
         let discovery_string = if config.disable_discovery {
@@ -121,7 +126,7 @@ impl Service {
 
         let mut swarm = {
             // Set up the transport - tcp/ws with noise and yamux/mplex
-            let transport = build_transport(local_keypair.clone())
+            let transport = build_transport(local_keypair.clone(), network_globals.clone())
                 .map_err(|e| format!("Failed to build transport: {:?}", e))?;
             // Mothra network behaviour
             let behaviour = Behaviour::new(&local_keypair, config, network_globals.clone(), &log)?;
@@ -205,22 +210,79 @@ impl Service {
             local_peer_id,
             swarm,
             network_globals: network_globals.clone(),
-            peers_to_ban: DelayQueue::new(),
-            peer_ban_timeout: DelayQueue::new(),
+            peers_to_ban: HashSetDelay::new(Duration::from_millis(BAN_PEER_WAIT_TIMEOUT)),
+            peer_ban_timeout: HashSetDelay::default(),
             log,
         };
 
         Ok((network_globals, service))
     }
 
+    /// Dials a multiaddr directly.
+    pub fn dial(&mut self, address: Multiaddr) {
+        if let Some(ip) = multiaddr_ip(&address) {
+            if !self.network_globals.peer_filter.read().is_ip_allowed(&ip) {
+                debug!(self.log, "Not dialing address rejected by the peer filter"; "address" => format!("{}", address));
+                return;
+            }
+        }
+        match Swarm::dial_addr(&mut self.swarm, address.clone()) {
+            Ok(()) => debug!(self.log, "Dialing libp2p peer"; "address" => format!("{}", address)),
+            Err(err) => debug!(
+                self.log,
+                "Could not connect to peer"; "address" => format!("{}", address), "error" => format!("{:?}", err)
+            ),
+        };
+    }
+
+    /// Adds an ENR to the discovery routing table and dials the peer it belongs to.
+    pub fn dial_enr(&mut self, enr: Enr) {
+        let peer_id = enr.peer_id();
+        self.swarm.add_enr(enr);
+        self.swarm.peer_manager().dial_peer(&peer_id);
+    }
+
+    /// Sends a Goodbye with the given reason to a peer and disconnects it, without banning it.
+    pub fn disconnect_peer(&mut self, peer_id: PeerId, reason: GoodbyeReason) {
+        self.swarm.peer_manager().disconnect_peer(&peer_id, reason);
+    }
+
     /// Adds a peer to be banned for a period of time, specified by a timeout.
     pub fn disconnect_and_ban_peer(&mut self, peer_id: PeerId, timeout: Duration) {
         warn!(self.log, "Disconnecting and banning peer"; "peer_id" => peer_id.to_string(), "timeout" => format!("{:?}", timeout));
-        self.peers_to_ban.insert(
-            peer_id.clone(),
-            Duration::from_millis(BAN_PEER_WAIT_TIMEOUT),
-        );
-        self.peer_ban_timeout.insert(peer_id, timeout);
+        self.swarm
+            .peer_manager()
+            .disconnect_peer(&peer_id, GoodbyeReason::Fault);
+        self.peers_to_ban.insert(peer_id.clone());
+        self.peer_ban_timeout.insert_at(peer_id, timeout);
+    }
+
+    /// Lifts the ban of a peer, including any ban that is still pending.
+    pub fn unban_peer(&mut self, peer_id: PeerId) {
+        debug!(self.log, "Unbanning peer"; "peer_id" => peer_id.to_string());
+        self.peers_to_ban.remove(&peer_id);
+        self.peer_ban_timeout.remove(&peer_id);
+        self.swarm.peer_unbanned(&peer_id);
+        Swarm::unban_peer_id(&mut self.swarm, peer_id);
+    }
+
+    /// Adds an entry to the allow or deny list and disconnects the peers that are no longer
+    /// permitted.
+    pub fn add_filter_entry(&mut self, list: FilterList, entry: FilterEntry) {
+        debug!(self.log, "Adding peer filter entry"; "list" => format!("{:?}", list), "entry" => entry.to_string());
+        self.network_globals.peer_filter.write().insert(list, entry);
+        self.swarm.peer_manager().apply_peer_filter();
+    }
+
+    /// Removes an entry from the allow or deny list and disconnects the peers that are no longer
+    /// permitted.
+    pub fn remove_filter_entry(&mut self, list: FilterList, entry: FilterEntry) {
+        debug!(self.log, "Removing peer filter entry"; "list" => format!("{:?}", list), "entry" => entry.to_string());
+        self.network_globals
+            .peer_filter
+            .write()
+            .remove(list, &entry);
+        self.swarm.peer_manager().apply_peer_filter();
     }
 
     /// Sends a request to a peer, with a given Id.
@@ -236,7 +298,7 @@ impl Service {
         error: RPCResponseErrorCode,
         reason: String,
     ) {
-        self.swarm._send_error_reponse(peer_id, id, error, reason);
+        self.swarm.send_error_response(peer_id, id, error, reason);
     }
 
     /// Sends a response to a peer's request.
@@ -249,6 +311,9 @@ impl Service {
             tokio::select! {
                 event = self.swarm.next_event() => {
                     match event {
+                        SwarmEvent::Behaviour(BehaviourEvent::BanPeer(peer_id, duration)) => {
+                            self.disconnect_and_ban_peer(peer_id, duration);
+                        }
                         SwarmEvent::Behaviour(behaviour) => {
                             return Libp2pEvent::Behaviour(behaviour)
                         }
@@ -262,17 +327,19 @@ impl Service {
                             // has been established and update the db
                             if num_established.get() == 1 {
                                 // update the peerdb
-                                match endpoint {
+                                let address = endpoint.get_remote_address().clone();
+                                let accepted = match endpoint {
                                     ConnectedPoint::Listener { .. } => {
-                                        self.swarm.peer_manager().connect_ingoing(&peer_id);
+                                        self.swarm.peer_manager().connect_ingoing(&peer_id, address)
                                     }
-                                    ConnectedPoint::Dialer { .. } => self
-                                        .network_globals
-                                        .peers
-                                        .write()
-                                        .connect_outgoing(&peer_id),
+                                    ConnectedPoint::Dialer { .. } => {
+                                        self.swarm.peer_manager().connect_outgoing(&peer_id, address)
+                                    }
+                                };
+                                // rejected peers are being disconnected, don't inform the network layer
+                                if accepted {
+                                    return Libp2pEvent::PeerConnected { peer_id, endpoint };
                                 }
-                                return Libp2pEvent::PeerConnected { peer_id, endpoint };
                             }
                         }
                         SwarmEvent::ConnectionClosed {
@@ -341,8 +408,7 @@ impl Service {
                         }
                     }
                 }
-                Some(Ok(peer_to_ban)) = self.peers_to_ban.next() => {
-                    let peer_id = peer_to_ban.into_inner();
+                Some(Ok(peer_id)) = self.peers_to_ban.next() => {
                     Swarm::ban_peer_id(&mut self.swarm, peer_id.clone());
                     // TODO: Correctly notify protocols of the disconnect
                     // TODO: Also remove peer from the DHT: https://github.com/sigp/lighthouse/issues/629
@@ -350,9 +416,8 @@ impl Service {
                     // inform the behaviour that the peer has been banned
                     self.swarm.peer_banned(peer_id);
                 }
-                Some(Ok(peer_to_unban)) = self.peer_ban_timeout.next() => {
-                    debug!(self.log, "Peer has been unbanned"; "peer" => format!("{:?}", peer_to_unban));
-                    let unban_peer = peer_to_unban.into_inner();
+                Some(Ok(unban_peer)) = self.peer_ban_timeout.next() => {
+                    debug!(self.log, "Peer has been unbanned"; "peer" => format!("{:?}", unban_peer));
                     self.swarm.peer_unbanned(&unban_peer);
                     Swarm::unban_peer_id(&mut self.swarm, unban_peer);
                 }
@@ -363,9 +428,12 @@ impl Service {
 
 /// The implementation supports TCP/IP, WebSockets over TCP/IP, noise as the encryption layer, and
 /// yamux or mplex as the multiplexing layer.
-
+///
+/// Connections to or from peers that are not permitted by the allow and deny lists or the
+/// application's connection gate are dropped before they reach the swarm.
 fn build_transport(
     local_private_key: Keypair,
+    network_globals: Arc<NetworkGlobals>,
 ) -> Result<Boxed<(PeerId, StreamMuxerBox), Error>, Error> {
     let transport = libp2p::tcp::TokioTcpConfig::new().nodelay(true);
     let transport = libp2p::dns::DnsConfig::new(transport)?;
@@ -413,6 +481,31 @@ fn build_transport(
                 .map_ok(|(id, muxer)| (id, core::muxing::StreamMuxerBox::new(muxer)))
         })
         .timeout(Duration::from_secs(20))
+        .map_err(|err| Error::new(ErrorKind::Other, err));
+
+    // Connection gating
+    let transport = transport
+        .and_then(move |(peer_id, muxer), endpoint| {
+            let direction = match endpoint {
+                ConnectedPoint::Dialer { .. } => ConnectionDirection::Outgoing,
+                ConnectedPoint::Listener { .. } => ConnectionDirection::Incoming,
+            };
+            let allowed = network_globals.connection_allowed(
+                &peer_id,
+                endpoint.get_remote_address(),
+                direction,
+            );
+            async move {
+                if allowed {
+                    Ok((peer_id, muxer))
+                } else {
+                    Err(Error::new(
+                        ErrorKind::PermissionDenied,
+                        format!("Connection with peer {} rejected", peer_id),
+                    ))
+                }
+            }
+        })
         .map_err(|err| Error::new(ErrorKind::Other, err))
         .boxed();
     Ok(transport)
@@ -0,0 +1,167 @@
+//! Encryption of the messages of private topics with symmetric keys shared out of band.
+//!
+//! Messages are encrypted with ChaCha20-Poly1305. The encrypted message is the id of the key as a
+//! big-endian `u32`, a random 12 byte nonce and the ciphertext, authenticated together with the
+//! topic and the key id.
+
+use crate::config::TopicKey;
+use crate::TopicHash;
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use rand::RngCore;
+use std::convert::TryInto;
+
+/// The length of the key id preceding the nonce.
+const KEY_ID_LENGTH: usize = 4;
+const NONCE_LENGTH: usize = 12;
+const KEY_LENGTH: usize = 32;
+
+/// Encrypts and decrypts the messages of a private topic.
+pub struct TopicCipher {
+    /// The ciphers of the keys of the topic by key id. The first one encrypts.
+    keys: Vec<(u32, ChaCha20Poly1305)>,
+}
+
+impl TopicCipher {
+    /// Builds the ciphers of the keys of a topic, of which there must be at least one.
+    pub fn new(keys: &[TopicKey]) -> Result<Self, String> {
+        if keys.is_empty() {
+            return Err("A private topic needs at least one key".into());
+        }
+        let keys = keys
+            .iter()
+            .map(|key| {
+                let key_bytes = hex::decode(key.key_hex.trim_start_matches("0x"))
+                    .map_err(|e| format!("Invalid hex of topic key {}: {}", key.id, e))?;
+                if key_bytes.len() != KEY_LENGTH {
+                    return Err(format!(
+                        "Topic key {} must be {} bytes long, not {}",
+                        key.id,
+                        KEY_LENGTH,
+                        key_bytes.len()
+                    ));
+                }
+                let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(&key_bytes));
+                Ok((key.id, cipher))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(TopicCipher { keys })
+    }
+
+    /// Encrypts a message published on `topic` with the first key.
+    pub fn encrypt(&self, topic: &TopicHash, message: &[u8]) -> Result<Vec<u8>, String> {
+        let (key_id, cipher) = &self.keys[0];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let payload = Payload {
+            msg: message,
+            aad: &associated_data(topic, *key_id),
+        };
+        let ciphertext = cipher
+            .encrypt(GenericArray::from_slice(&nonce), payload)
+            .map_err(|_| "Could not encrypt message".to_string())?;
+
+        let mut data = Vec::with_capacity(KEY_ID_LENGTH + NONCE_LENGTH + ciphertext.len());
+        data.extend_from_slice(&key_id.to_be_bytes());
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&ciphertext);
+        Ok(data)
+    }
+
+    /// Decrypts a message received on `topic` with the key it names.
+    pub fn decrypt(&self, topic: &TopicHash, data: &[u8]) -> Result<Vec<u8>, String> {
+        if data.len() < KEY_ID_LENGTH + NONCE_LENGTH {
+            return Err(format!("Encrypted message too short: {} bytes", data.len()));
+        }
+        let (key_id, rest) = data.split_at(KEY_ID_LENGTH);
+        let key_id = u32::from_be_bytes(key_id.try_into().expect("length checked above"));
+        let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+        let cipher = self
+            .keys
+            .iter()
+            .find(|(id, _)| *id == key_id)
+            .map(|(_, cipher)| cipher)
+            .ok_or_else(|| format!("Unknown topic key: {}", key_id))?;
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &associated_data(topic, key_id),
+        };
+        cipher
+            .decrypt(GenericArray::from_slice(nonce), payload)
+            .map_err(|_| "Could not decrypt message".to_string())
+    }
+}
+
+/// Binds a ciphertext to its topic and key id.
+fn associated_data(topic: &TopicHash, key_id: u32) -> Vec<u8> {
+    let mut data = topic.as_str().as_bytes().to_vec();
+    data.extend_from_slice(&key_id.to_be_bytes());
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(id: u32, byte: u8) -> TopicKey {
+        TopicKey {
+            id,
+            key_hex: hex::encode([byte; KEY_LENGTH]),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let topic = TopicHash::from_raw("/mothra/private");
+        let cipher = TopicCipher::new(&[key(1, 7)]).unwrap();
+        let data = cipher.encrypt(&topic, b"secret").unwrap();
+        assert!(!data.windows(6).any(|window| window == b"secret"));
+        assert_eq!(cipher.decrypt(&topic, &data).unwrap(), b"secret".to_vec());
+
+        // messages are bound to their topic and authenticated
+        assert!(cipher
+            .decrypt(&TopicHash::from_raw("/mothra/other"), &data)
+            .is_err());
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(cipher.decrypt(&topic, &tampered).is_err());
+        assert!(cipher.decrypt(&topic, &data[..10]).is_err());
+
+        // the nonce is random
+        assert_ne!(cipher.encrypt(&topic, b"secret").unwrap(), data);
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let topic = TopicHash::from_raw("/mothra/private");
+        let old = TopicCipher::new(&[key(1, 7)]).unwrap();
+        let rotated = TopicCipher::new(&[key(2, 8), key(1, 7)]).unwrap();
+
+        let data = old.encrypt(&topic, b"secret").unwrap();
+        assert_eq!(rotated.decrypt(&topic, &data).unwrap(), b"secret".to_vec());
+        let data = rotated.encrypt(&topic, b"secret").unwrap();
+        assert!(old.decrypt(&topic, &data).is_err());
+
+        // a wrong key with the right id does not decrypt
+        let wrong = TopicCipher::new(&[key(1, 9)]).unwrap();
+        assert!(wrong
+            .decrypt(&topic, &old.encrypt(&topic, b"secret").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(TopicCipher::new(&[]).is_err());
+        assert!(TopicCipher::new(&[TopicKey {
+            id: 1,
+            key_hex: "0102".into()
+        }])
+        .is_err());
+        assert!(TopicCipher::new(&[TopicKey {
+            id: 1,
+            key_hex: "not hex".into()
+        }])
+        .is_err());
+    }
+}
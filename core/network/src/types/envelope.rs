@@ -0,0 +1,86 @@
+//! An opt-in envelope carrying the time a gossipsub message was published, so that receivers can
+//! drop the messages that are older than the time to live of their topic.
+//!
+//! The envelope is the publication time in milliseconds since the unix epoch, as a big-endian
+//! `u64`, followed by the message.
+
+use std::convert::TryInto;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The length of the timestamp preceding the message.
+const TIMESTAMP_LENGTH: usize = 8;
+/// How far ahead of ours the clock of a publisher may be.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(5);
+
+/// Wraps a message published at `published` in an envelope.
+pub fn seal(message: Vec<u8>, published: SystemTime) -> Vec<u8> {
+    let timestamp_ms = published
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_millis() as u64);
+    let mut data = Vec::with_capacity(TIMESTAMP_LENGTH + message.len());
+    data.extend_from_slice(&timestamp_ms.to_be_bytes());
+    data.extend_from_slice(&message);
+    data
+}
+
+/// Unwraps a received message, returning the time it was published and the message.
+pub fn open(mut data: Vec<u8>) -> Result<(SystemTime, Vec<u8>), String> {
+    if data.len() < TIMESTAMP_LENGTH {
+        return Err(format!(
+            "Message too short for a timestamp envelope: {} bytes",
+            data.len()
+        ));
+    }
+    let message = data.split_off(TIMESTAMP_LENGTH);
+    let timestamp_ms = u64::from_be_bytes(data[..].try_into().expect("length checked above"));
+    Ok((UNIX_EPOCH + Duration::from_millis(timestamp_ms), message))
+}
+
+/// How long ago a message was published. Messages published slightly in the future, by a peer
+/// whose clock is ahead, are not aged, and the ones further in the future are invalid as they
+/// would never expire.
+pub fn age(published: SystemTime, now: SystemTime) -> Result<Duration, String> {
+    match now.duration_since(published) {
+        Ok(age) => Ok(age),
+        Err(e) if e.duration() <= MAX_CLOCK_SKEW => Ok(Duration::default()),
+        Err(e) => Err(format!(
+            "Message published {:?} in the future",
+            e.duration()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let published = UNIX_EPOCH + Duration::from_millis(1_600_000_000_123);
+        let data = seal(b"block".to_vec(), published);
+        assert_eq!(data.len(), TIMESTAMP_LENGTH + 5);
+        assert_eq!(open(data), Ok((published, b"block".to_vec())));
+
+        assert_eq!(open(seal(vec![], published)), Ok((published, Vec::new())));
+        assert!(open(vec![0; TIMESTAMP_LENGTH - 1]).is_err());
+    }
+
+    #[test]
+    fn test_age() {
+        let published = UNIX_EPOCH + Duration::from_secs(100);
+        assert_eq!(
+            age(published, published + Duration::from_secs(3)),
+            Ok(Duration::from_secs(3))
+        );
+        // a message from a peer whose clock is ahead
+        assert_eq!(
+            age(published, published - Duration::from_secs(3)),
+            Ok(Duration::from_secs(0))
+        );
+        assert!(age(
+            published,
+            published - MAX_CLOCK_SKEW - Duration::from_secs(1)
+        )
+        .is_err());
+    }
+}
@@ -1,11 +1,18 @@
 //! A collection of variables that are accessible outside of the network thread itself.
+use crate::peer_manager::{multiaddr_ip, ConnectionDirection, ConnectionGate, PeerFilter};
 use crate::rpc::methods::MetaData;
 use crate::Client;
 use crate::EnrExt;
 use crate::{Enr, EnrForkId, GossipTopic, Multiaddr, PeerDB, PeerId};
+use libp2p::gossipsub::TopicHash;
 use parking_lot::RwLock;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long after forwarding a message on a topic a peer is considered active on it.
+const TOPIC_ACTIVITY_TIMEOUT: Duration = Duration::from_secs(60);
 
 pub struct NetworkGlobals {
     /// The current local ENR.
@@ -28,6 +35,15 @@ pub struct NetworkGlobals {
     pub peers: RwLock<PeerDB>,
     /// The current gossipsub topic subscriptions.
     pub gossipsub_subscriptions: RwLock<HashSet<GossipTopic>>,
+    /// The connected peers subscribed to each gossipsub topic.
+    pub topic_peers: RwLock<HashMap<TopicHash, HashSet<PeerId>>>,
+    /// When the peers last forwarded us a message on each subscribed topic. Gossipsub does not
+    /// expose its mesh, so this approximates it.
+    pub topic_activity: RwLock<HashMap<TopicHash, HashMap<PeerId, Instant>>>,
+    /// The allow and deny lists used to gate connections.
+    pub peer_filter: RwLock<PeerFilter>,
+    /// The application's hook deciding whether a connection is accepted, if any.
+    pub connection_gate: RwLock<Option<ConnectionGate>>,
 }
 
 impl NetworkGlobals {
@@ -38,6 +54,7 @@ impl NetworkGlobals {
         ping_data: Vec<u8>,
         tcp_port: u16,
         udp_port: u16,
+        peer_filter: PeerFilter,
         log: &slog::Logger,
     ) -> Self {
         NetworkGlobals {
@@ -51,6 +68,10 @@ impl NetworkGlobals {
             listen_port_udp: AtomicU16::new(udp_port),
             peers: RwLock::new(PeerDB::new(log)),
             gossipsub_subscriptions: RwLock::new(HashSet::new()),
+            topic_peers: RwLock::new(HashMap::new()),
+            topic_activity: RwLock::new(HashMap::new()),
+            peer_filter: RwLock::new(peer_filter),
+            connection_gate: RwLock::new(None),
         }
     }
 
@@ -96,6 +117,31 @@ impl NetworkGlobals {
         self.peers.read().connected_or_dialing_peers().count()
     }
 
+    /// Returns true if the peer, connecting from the given IP (if known), passes the allow and
+    /// deny lists.
+    pub fn peer_allowed(&self, peer_id: &PeerId, ip: Option<&IpAddr>) -> bool {
+        self.peer_filter.read().is_allowed(peer_id, ip)
+    }
+
+    /// Returns true if a connection with the peer at the given address passes both the allow and
+    /// deny lists and the application's connection gate.
+    pub fn connection_allowed(
+        &self,
+        peer_id: &PeerId,
+        address: &Multiaddr,
+        direction: ConnectionDirection,
+    ) -> bool {
+        if !self.peer_allowed(peer_id, multiaddr_ip(address).as_ref()) {
+            return false;
+        }
+        // release the lock before handing control to the application
+        let connection_gate = self.connection_gate.read().clone();
+        match connection_gate {
+            Some(gate) => gate(peer_id, address, direction, &self.client(peer_id)),
+            None => true,
+        }
+    }
+
     /// Returns a `Client` type if one is known for the `PeerId`.
     pub fn client(&self, peer_id: &PeerId) -> Client {
         self.peers
@@ -104,4 +150,112 @@ impl NetworkGlobals {
             .map(|info| info.client.clone())
             .unwrap_or_default()
     }
+
+    /// Returns the connected peers subscribed to a topic.
+    pub fn topic_peers(&self, topic: &GossipTopic) -> Vec<PeerId> {
+        self.topic_peers
+            .read()
+            .get(&topic.topic_hash())
+            .map(|peers| peers.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the connected peers subscribed to a topic that forwarded us a message on it within
+    /// the last minute. This is a heuristic for our mesh, which gossipsub does not expose: peers
+    /// answering our gossip requests or sending fanout messages can appear as well, and mesh
+    /// peers of quiet topics may be missing.
+    pub fn active_topic_peers(&self, topic: &GossipTopic) -> Vec<PeerId> {
+        let topic_hash = topic.topic_hash();
+        let topic_peers = self.topic_peers.read();
+        let subscribed = match topic_peers.get(&topic_hash) {
+            Some(subscribed) => subscribed,
+            None => return vec![],
+        };
+        self.topic_activity
+            .read()
+            .get(&topic_hash)
+            .map(|activity| {
+                activity
+                    .iter()
+                    .filter(|(peer_id, forwarded)| {
+                        subscribed.contains(*peer_id)
+                            && forwarded.elapsed() < TOPIC_ACTIVITY_TIMEOUT
+                    })
+                    .map(|(peer_id, _)| peer_id.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Records that a peer subscribed to a topic.
+    pub(crate) fn add_topic_peer(&self, topic: TopicHash, peer_id: PeerId) {
+        self.topic_peers
+            .write()
+            .entry(topic)
+            .or_default()
+            .insert(peer_id);
+    }
+
+    /// Records that a peer unsubscribed from a topic.
+    pub(crate) fn remove_topic_peer(&self, topic: &TopicHash, peer_id: &PeerId) {
+        let mut topic_peers = self.topic_peers.write();
+        if let Some(peers) = topic_peers.get_mut(topic) {
+            peers.remove(peer_id);
+            if peers.is_empty() {
+                topic_peers.remove(topic);
+            }
+        }
+        let mut topic_activity = self.topic_activity.write();
+        if let Some(activity) = topic_activity.get_mut(topic) {
+            activity.remove(peer_id);
+            if activity.is_empty() {
+                topic_activity.remove(topic);
+            }
+        }
+    }
+
+    /// Forgets the topics of a disconnected peer.
+    pub(crate) fn remove_peer_topics(&self, peer_id: &PeerId) {
+        self.topic_peers.write().retain(|_, peers| {
+            peers.remove(peer_id);
+            !peers.is_empty()
+        });
+        self.topic_activity.write().retain(|_, activity| {
+            activity.remove(peer_id);
+            !activity.is_empty()
+        });
+    }
+
+    /// Records that a peer forwarded us a message on some topics. Only the topics we are
+    /// subscribed to are recorded, as the others are chosen by the sender.
+    pub(crate) fn record_topic_activity(&self, topics: &[TopicHash], peer_id: &PeerId) {
+        let subscriptions = self.gossipsub_subscriptions.read();
+        let mut topic_activity = self.topic_activity.write();
+        for topic in topics.iter().filter(|topic_hash| {
+            subscriptions
+                .iter()
+                .any(|topic| topic.topic_hash() == **topic_hash)
+        }) {
+            topic_activity
+                .entry(topic.clone())
+                .or_default()
+                .insert(peer_id.clone(), Instant::now());
+        }
+    }
+
+    /// Maps the topic hashes of a gossipsub message back to the subscribed topics. Topics that
+    /// are not subscribed to are kept as given by their hash.
+    pub fn gossip_topics(&self, topic_hashes: &[TopicHash]) -> Vec<GossipTopic> {
+        let subscriptions = self.gossipsub_subscriptions.read();
+        topic_hashes
+            .iter()
+            .map(|topic_hash| {
+                subscriptions
+                    .iter()
+                    .find(|topic| topic.topic_hash() == *topic_hash)
+                    .cloned()
+                    .unwrap_or_else(|| GossipTopic::new(topic_hash.to_string()))
+            })
+            .collect()
+    }
 }
@@ -1,9 +1,12 @@
+pub(crate) mod encryption;
+pub(crate) mod envelope;
 pub mod error;
 mod globals;
+pub(crate) mod signing;
 mod topics;
 
 pub use globals::NetworkGlobals;
-pub use topics::GossipTopic;
+pub use topics::{Eth2Kind, Eth2Topic, GossipEncoding, GossipTopic, TopicPattern};
 
 #[allow(type_alias_bounds)]
 pub type EnrBitfield = Vec<u8>;
@@ -0,0 +1,186 @@
+//! Signatures of gossipsub messages by their author.
+//!
+//! Gossipsub does not sign messages (see `Behaviour`). Signed messages are wrapped in an envelope
+//! holding the public key of the author and a signature of the topics and the message, which
+//! receivers verify against the author gossipsub reports. Envelopes are neither added nor opened
+//! under the anonymous policy, whose messages are exchanged as given by the application.
+//!
+//! The sequence number is not signed, and signatures do not make messages fresh: a signed message
+//! can be replayed once gossipsub forgot its id, unless it carries a timestamp envelope.
+//!
+//! The envelope is a 4 byte marker, the length of the protobuf encoded public key as a big-endian
+//! `u16`, the key, the length of the signature as a big-endian `u16`, the signature and the
+//! message.
+
+use crate::config::SigningPolicy;
+use crate::{PeerId, TopicHash};
+use libp2p::core::identity::{Keypair, PublicKey};
+use std::convert::TryInto;
+
+/// Starts the envelope of signed messages.
+const SIGNATURE_MARKER: [u8; 4] = *b"MSIG";
+/// Prefixes the signed bytes, so that the signatures cannot be used in another context.
+const SIGNING_DOMAIN: &[u8] = b"mothra-gossip:";
+
+/// A received message, with its author if it is signed.
+#[derive(Debug, PartialEq)]
+pub enum Authorship {
+    /// The message carries no signature.
+    Unsigned(Vec<u8>),
+    /// The message was signed by its author.
+    Signed { author: PeerId, message: Vec<u8> },
+}
+
+/// Signs a message published on `topics` and wraps it in an envelope, unless messages are
+/// anonymous.
+pub fn seal(
+    policy: SigningPolicy,
+    keypair: &Keypair,
+    topics: &[TopicHash],
+    message: Vec<u8>,
+) -> Result<Vec<u8>, String> {
+    if policy == SigningPolicy::Anonymous {
+        return Ok(message);
+    }
+    let signature = keypair
+        .sign(&signed_bytes(topics, &message))
+        .map_err(|e| format!("Could not sign message: {}", e))?;
+    let key = keypair.public().into_protobuf_encoding();
+
+    let mut data = Vec::with_capacity(8 + key.len() + signature.len() + message.len());
+    data.extend_from_slice(&SIGNATURE_MARKER);
+    push_field(&mut data, &key)?;
+    push_field(&mut data, &signature)?;
+    data.extend_from_slice(&message);
+    Ok(data)
+}
+
+/// Unwraps a message received on `topics` whose author is `source` according to gossipsub.
+/// Messages without an envelope, and all messages when they are anonymous, are returned
+/// unchanged. The signatures of the others must verify with the key of `source`.
+pub fn open(
+    policy: SigningPolicy,
+    data: Vec<u8>,
+    topics: &[TopicHash],
+    source: &PeerId,
+) -> Result<Authorship, String> {
+    if policy == SigningPolicy::Anonymous || !data.starts_with(&SIGNATURE_MARKER) {
+        return Ok(Authorship::Unsigned(data));
+    }
+    let rest = &data[SIGNATURE_MARKER.len()..];
+    let (key, rest) = read_field(rest)?;
+    let (signature, message) = read_field(rest)?;
+
+    let key = PublicKey::from_protobuf_encoding(key)
+        .map_err(|e| format!("Invalid signing key: {}", e))?;
+    if key.clone().into_peer_id() != *source {
+        return Err("The message is not signed by its author".into());
+    }
+    if !key.verify(&signed_bytes(topics, message), signature) {
+        return Err("Invalid message signature".into());
+    }
+    Ok(Authorship::Signed {
+        author: source.clone(),
+        message: message.to_vec(),
+    })
+}
+
+/// The signing domain, the length prefixed topics and the message.
+fn signed_bytes(topics: &[TopicHash], message: &[u8]) -> Vec<u8> {
+    let mut bytes = SIGNING_DOMAIN.to_vec();
+    for topic in topics {
+        bytes.extend_from_slice(&(topic.as_str().len() as u64).to_be_bytes());
+        bytes.extend_from_slice(topic.as_str().as_bytes());
+    }
+    bytes.extend_from_slice(message);
+    bytes
+}
+
+fn push_field(data: &mut Vec<u8>, field: &[u8]) -> Result<(), String> {
+    let length: u16 = field
+        .len()
+        .try_into()
+        .map_err(|_| format!("Signature field too long: {} bytes", field.len()))?;
+    data.extend_from_slice(&length.to_be_bytes());
+    data.extend_from_slice(field);
+    Ok(())
+}
+
+fn read_field(data: &[u8]) -> Result<(&[u8], &[u8]), String> {
+    if data.len() < 2 {
+        return Err("Truncated signature envelope".into());
+    }
+    let length = u16::from_be_bytes([data[0], data[1]]) as usize;
+    if data.len() < 2 + length {
+        return Err("Truncated signature envelope".into());
+    }
+    Ok((&data[2..2 + length], &data[2 + length..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topics(names: &[&str]) -> Vec<TopicHash> {
+        names
+            .iter()
+            .map(|name| TopicHash::from_raw(*name))
+            .collect()
+    }
+
+    fn open_signed(
+        data: Vec<u8>,
+        topics: &[TopicHash],
+        source: &PeerId,
+    ) -> Result<Authorship, String> {
+        open(SigningPolicy::Signed, data, topics, source)
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let keypair = Keypair::generate_secp256k1();
+        let author = keypair.public().into_peer_id();
+        let blocks = topics(&["blocks"]);
+        let data = seal(SigningPolicy::Signed, &keypair, &blocks, b"block".to_vec()).unwrap();
+
+        assert_eq!(
+            open_signed(data.clone(), &blocks, &author),
+            Ok(Authorship::Signed {
+                author: author.clone(),
+                message: b"block".to_vec()
+            })
+        );
+        // the signature covers the topics
+        assert!(open_signed(data.clone(), &topics(&["attestations"]), &author).is_err());
+        // and must be the one of the author gossipsub reports
+        assert!(open_signed(data.clone(), &blocks, &PeerId::random()).is_err());
+
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open_signed(tampered, &blocks, &author).is_err());
+        assert!(open_signed(data[..data.len() - 6].to_vec(), &blocks, &author).is_err());
+        assert!(open_signed(SIGNATURE_MARKER.to_vec(), &blocks, &author).is_err());
+    }
+
+    #[test]
+    fn test_unsigned() {
+        assert_eq!(
+            open_signed(b"block".to_vec(), &topics(&["blocks"]), &PeerId::random()),
+            Ok(Authorship::Unsigned(b"block".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_anonymous() {
+        let keypair = Keypair::generate_secp256k1();
+        let blocks = topics(&["blocks"]);
+        // payloads starting with the marker are not taken for envelopes
+        let message = b"MSIG payload".to_vec();
+        let data = seal(SigningPolicy::Anonymous, &keypair, &blocks, message.clone()).unwrap();
+        assert_eq!(data, message);
+        assert_eq!(
+            open(SigningPolicy::Anonymous, data, &blocks, &PeerId::random()),
+            Ok(Authorship::Unsigned(message))
+        );
+    }
+}
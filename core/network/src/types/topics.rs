@@ -1,15 +1,315 @@
-use libp2p::gossipsub::Topic;
+use crate::types::SubnetId;
+use libp2p::gossipsub::{Topic, TopicHash};
 use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
+/// The first component of eth2 topic names.
+pub const TOPIC_PREFIX: &str = "eth2";
+/// The postfix of topics whose messages are ssz encoded.
+pub const SSZ_ENCODING_POSTFIX: &str = "ssz";
+/// The postfix of topics whose messages are ssz encoded and raw snappy compressed.
+pub const SSZ_SNAPPY_ENCODING_POSTFIX: &str = "ssz_snappy";
+pub const BEACON_BLOCK_TOPIC: &str = "beacon_block";
+pub const BEACON_AGGREGATE_AND_PROOF_TOPIC: &str = "beacon_aggregate_and_proof";
+pub const BEACON_ATTESTATION_PREFIX: &str = "beacon_attestation_";
+pub const VOLUNTARY_EXIT_TOPIC: &str = "voluntary_exit";
+pub const PROPOSER_SLASHING_TOPIC: &str = "proposer_slashing";
+pub const ATTESTER_SLASHING_TOPIC: &str = "attester_slashing";
+/// The number of attestation subnets, `beacon_attestation_{n}` topics have `n` below it.
+pub const ATTESTATION_SUBNET_COUNT: SubnetId = 64;
+/// Matches any sequence of characters in a `TopicPattern`.
+pub const TOPIC_WILDCARD: char = '*';
+
+/// A gossipsub topic. Any topic name can be used, eth2 topics of the form
+/// `/eth2/{fork_digest}/{name}/{encoding}` can be built and inspected through `Eth2Topic`.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct GossipTopic {
     topic: String,
 }
 
+/// The name of an eth2 topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Eth2Kind {
+    BeaconBlock,
+    BeaconAggregateAndProof,
+    /// The attestations of a subnet.
+    Attestation(SubnetId),
+    VoluntaryExit,
+    ProposerSlashing,
+    AttesterSlashing,
+}
+
+/// A parsed `/eth2/{fork_digest}/{name}/{encoding}` topic.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Eth2Topic {
+    pub fork_digest: [u8; 4],
+    pub kind: Eth2Kind,
+    pub encoding: GossipEncoding,
+}
+
+/// A glob over topic names, where `*` matches any sequence of characters, such as
+/// `/eth2/*/beacon_attestation_*/ssz_snappy`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TopicPattern {
+    pattern: String,
+}
+
+/// The encoding of the messages of a topic, given by the last component of its name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GossipEncoding {
+    /// Messages are exchanged as given by the application.
+    Raw,
+    /// Messages are raw snappy compressed on the wire.
+    SSZSnappy,
+}
+
 impl GossipTopic {
     pub fn new(topic: String) -> Self {
         GossipTopic { topic }
     }
+
+    /// Builds the eth2 topic of a kind for a fork.
+    pub fn eth2(fork_digest: [u8; 4], kind: Eth2Kind, encoding: GossipEncoding) -> Self {
+        Eth2Topic {
+            fork_digest,
+            kind,
+            encoding,
+        }
+        .into()
+    }
+
+    /// Whether this topic is in the eth2 namespace.
+    pub fn is_eth2(&self) -> bool {
+        self.topic.starts_with(&format!("/{}/", TOPIC_PREFIX))
+    }
+
+    /// Parses this topic as an eth2 topic. Free-form topics give `None`, topics in the eth2
+    /// namespace that are not well formed give an error.
+    pub fn eth2_topic(&self) -> Result<Option<Eth2Topic>, String> {
+        if self.is_eth2() {
+            self.topic.parse().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Checks that the topic is not empty and, if it is an eth2 topic, that it is well formed.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.topic.is_empty() {
+            return Err("Empty topic".into());
+        }
+        self.eth2_topic().map(|_| ())
+    }
+
+    /// The same topic for another fork. Free-form topics are returned unchanged.
+    pub fn with_fork_digest(&self, fork_digest: [u8; 4]) -> Result<Self, String> {
+        match self.eth2_topic()? {
+            Some(topic) => Ok(Eth2Topic {
+                fork_digest,
+                ..topic
+            }
+            .into()),
+            None => Ok(self.clone()),
+        }
+    }
+
+    /// The hash gossipsub identifies this topic with. Topics are not hashed on the wire.
+    pub fn topic_hash(&self) -> TopicHash {
+        Topic::new(self.topic.clone()).no_hash()
+    }
+
+    /// The encoding of the messages of this topic.
+    pub fn encoding(&self) -> GossipEncoding {
+        GossipEncoding::from_topic(&self.topic)
+    }
+}
+
+impl GossipEncoding {
+    /// The encoding of a topic name, such as `/eth2/<fork digest>/beacon_block/ssz_snappy`.
+    pub fn from_topic(topic: &str) -> Self {
+        match topic.rsplit('/').next() {
+            Some(SSZ_SNAPPY_ENCODING_POSTFIX) => GossipEncoding::SSZSnappy,
+            _ => GossipEncoding::Raw,
+        }
+    }
+
+    /// Encodes a message to be published.
+    pub fn encode(self, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        match self {
+            GossipEncoding::Raw => Ok(data),
+            GossipEncoding::SSZSnappy => snap::raw::Encoder::new()
+                .compress_vec(&data)
+                .map_err(|e| format!("Could not compress message: {}", e)),
+        }
+    }
+
+    /// Decodes a received message. Messages that would decode to more than `max_size` bytes are
+    /// rejected before being decompressed.
+    pub fn decode(self, data: Vec<u8>, max_size: usize) -> Result<Vec<u8>, String> {
+        match self {
+            GossipEncoding::Raw => Ok(data),
+            GossipEncoding::SSZSnappy => {
+                let size = snap::raw::decompress_len(&data)
+                    .map_err(|e| format!("Invalid snappy message: {}", e))?;
+                if size > max_size {
+                    return Err(format!(
+                        "Decompressed message too large: {} > {} bytes",
+                        size, max_size
+                    ));
+                }
+                snap::raw::Decoder::new()
+                    .decompress_vec(&data)
+                    .map_err(|e| format!("Invalid snappy message: {}", e))
+            }
+        }
+    }
+}
+
+impl TopicPattern {
+    /// Parses a pattern, which must contain at least one wildcard.
+    pub fn new(pattern: String) -> Result<Self, String> {
+        if !Self::is_pattern(&pattern) {
+            return Err(format!("Not a topic pattern: {}", pattern));
+        }
+        Ok(TopicPattern { pattern })
+    }
+
+    /// Whether a topic name given by the user is a pattern rather than a topic.
+    pub fn is_pattern(topic: &str) -> bool {
+        topic.contains(TOPIC_WILDCARD)
+    }
+
+    /// Whether a topic name matches this pattern.
+    pub fn matches(&self, topic: &str) -> bool {
+        let mut parts = self.pattern.split(TOPIC_WILDCARD);
+        // the pattern contains a wildcard, so it has a first and a last part
+        let first = parts.next().unwrap_or_default();
+        if !topic.starts_with(first) {
+            return false;
+        }
+        let mut rest = &topic[first.len()..];
+        let mut parts = parts.collect::<Vec<_>>();
+        let last = parts.pop().unwrap_or_default();
+        for part in parts {
+            match rest.find(part) {
+                Some(index) => rest = &rest[index + part.len()..],
+                None => return false,
+            }
+        }
+        rest.ends_with(last)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl fmt::Display for TopicPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+impl fmt::Display for GossipTopic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.topic)
+    }
+}
+
+impl From<Eth2Topic> for GossipTopic {
+    fn from(topic: Eth2Topic) -> Self {
+        GossipTopic::new(topic.to_string())
+    }
+}
+
+impl fmt::Display for Eth2Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Eth2Kind::BeaconBlock => write!(f, "{}", BEACON_BLOCK_TOPIC),
+            Eth2Kind::BeaconAggregateAndProof => {
+                write!(f, "{}", BEACON_AGGREGATE_AND_PROOF_TOPIC)
+            }
+            Eth2Kind::Attestation(subnet_id) => {
+                write!(f, "{}{}", BEACON_ATTESTATION_PREFIX, subnet_id)
+            }
+            Eth2Kind::VoluntaryExit => write!(f, "{}", VOLUNTARY_EXIT_TOPIC),
+            Eth2Kind::ProposerSlashing => write!(f, "{}", PROPOSER_SLASHING_TOPIC),
+            Eth2Kind::AttesterSlashing => write!(f, "{}", ATTESTER_SLASHING_TOPIC),
+        }
+    }
+}
+
+impl FromStr for Eth2Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            BEACON_BLOCK_TOPIC => Ok(Eth2Kind::BeaconBlock),
+            BEACON_AGGREGATE_AND_PROOF_TOPIC => Ok(Eth2Kind::BeaconAggregateAndProof),
+            VOLUNTARY_EXIT_TOPIC => Ok(Eth2Kind::VoluntaryExit),
+            PROPOSER_SLASHING_TOPIC => Ok(Eth2Kind::ProposerSlashing),
+            ATTESTER_SLASHING_TOPIC => Ok(Eth2Kind::AttesterSlashing),
+            _ if s.starts_with(BEACON_ATTESTATION_PREFIX) => {
+                let index = &s[BEACON_ATTESTATION_PREFIX.len()..];
+                match index.parse::<SubnetId>() {
+                    // reject non-canonical indices such as `01` so that names round trip
+                    Ok(subnet_id)
+                        if subnet_id < ATTESTATION_SUBNET_COUNT
+                            && subnet_id.to_string() == index =>
+                    {
+                        Ok(Eth2Kind::Attestation(subnet_id))
+                    }
+                    _ => Err(format!("Invalid attestation subnet: {}", index)),
+                }
+            }
+            _ => Err(format!("Unknown eth2 topic name: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Eth2Topic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let encoding = match self.encoding {
+            GossipEncoding::Raw => SSZ_ENCODING_POSTFIX,
+            GossipEncoding::SSZSnappy => SSZ_SNAPPY_ENCODING_POSTFIX,
+        };
+        write!(
+            f,
+            "/{}/{}/{}/{}",
+            TOPIC_PREFIX,
+            hex::encode(self.fork_digest),
+            self.kind,
+            encoding
+        )
+    }
+}
+
+impl FromStr for Eth2Topic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('/').collect();
+        let (fork_digest, kind, encoding) = match parts.as_slice() {
+            ["", TOPIC_PREFIX, fork_digest, kind, encoding] => (fork_digest, kind, encoding),
+            _ => return Err(format!("Not an eth2 topic: {}", s)),
+        };
+
+        let mut digest = [0u8; 4];
+        hex::decode_to_slice(fork_digest, &mut digest)
+            .map_err(|_| format!("Invalid fork digest: {}", fork_digest))?;
+        let encoding = match *encoding {
+            SSZ_ENCODING_POSTFIX => GossipEncoding::Raw,
+            SSZ_SNAPPY_ENCODING_POSTFIX => GossipEncoding::SSZSnappy,
+            _ => return Err(format!("Unknown topic encoding: {}", encoding)),
+        };
+
+        Ok(Eth2Topic {
+            fork_digest: digest,
+            kind: kind.parse()?,
+            encoding,
+        })
+    }
 }
 
 impl Into<Topic> for GossipTopic {
@@ -23,3 +323,129 @@ impl Into<String> for GossipTopic {
         self.topic
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding_from_topic() {
+        assert_eq!(
+            GossipEncoding::from_topic("/eth2/00000000/beacon_block/ssz_snappy"),
+            GossipEncoding::SSZSnappy
+        );
+        assert_eq!(
+            GossipEncoding::from_topic("/eth2/00000000/beacon_block/ssz"),
+            GossipEncoding::Raw
+        );
+        assert_eq!(GossipEncoding::from_topic("blocks"), GossipEncoding::Raw);
+    }
+
+    #[test]
+    fn test_snappy_round_trip() {
+        let encoding = GossipEncoding::SSZSnappy;
+        let encoded = encoding.encode(vec![7; 1000]).unwrap();
+        assert!(encoded.len() < 1000);
+        assert_eq!(
+            encoding.decode(encoded.clone(), 1000).unwrap(),
+            vec![7; 1000]
+        );
+
+        // the size limit applies to the decompressed message
+        assert!(encoding.decode(encoded, 999).is_err());
+        assert!(encoding.decode(vec![0xff; 10], 1000).is_err());
+    }
+
+    #[test]
+    fn test_eth2_topic_round_trip() {
+        let kinds = vec![
+            Eth2Kind::BeaconBlock,
+            Eth2Kind::BeaconAggregateAndProof,
+            Eth2Kind::Attestation(0),
+            Eth2Kind::Attestation(63),
+            Eth2Kind::VoluntaryExit,
+            Eth2Kind::ProposerSlashing,
+            Eth2Kind::AttesterSlashing,
+        ];
+        for kind in kinds {
+            for encoding in vec![GossipEncoding::Raw, GossipEncoding::SSZSnappy] {
+                let topic = GossipTopic::eth2([0xe7, 0xa7, 0x5d, 0x5a], kind, encoding);
+                let parsed = topic.eth2_topic().unwrap().unwrap();
+                assert_eq!(parsed.kind, kind);
+                assert_eq!(parsed.encoding, encoding);
+                assert_eq!(topic.encoding(), encoding);
+                assert_eq!(GossipTopic::from(parsed), topic);
+            }
+        }
+
+        let topic = GossipTopic::eth2(
+            [0, 0, 0, 1],
+            Eth2Kind::Attestation(12),
+            GossipEncoding::SSZSnappy,
+        );
+        assert_eq!(
+            topic.to_string(),
+            "/eth2/00000001/beacon_attestation_12/ssz_snappy"
+        );
+    }
+
+    #[test]
+    fn test_eth2_topic_validation() {
+        let invalid = vec![
+            "",
+            "/eth2/0000/beacon_block/ssz",
+            "/eth2/zzzzzzzz/beacon_block/ssz",
+            "/eth2/00000000/beacon_blocks/ssz",
+            "/eth2/00000000/beacon_block/json",
+            "/eth2/00000000/beacon_attestation_64/ssz",
+            "/eth2/00000000/beacon_attestation_01/ssz",
+            "/eth2/00000000/beacon_block/ssz/extra",
+        ];
+        for topic in invalid {
+            assert!(
+                GossipTopic::new(topic.into()).validate().is_err(),
+                "{}",
+                topic
+            );
+        }
+
+        // free-form topics are not parsed
+        let free_form = GossipTopic::new("blocks".into());
+        assert!(free_form.validate().is_ok());
+        assert_eq!(free_form.eth2_topic(), Ok(None));
+        assert_eq!(free_form.with_fork_digest([1, 2, 3, 4]), Ok(free_form));
+    }
+
+    #[test]
+    fn test_topic_pattern() {
+        let attestations =
+            TopicPattern::new("/eth2/*/beacon_attestation_*/ssz_snappy".into()).unwrap();
+        assert!(attestations.matches("/eth2/00000000/beacon_attestation_0/ssz_snappy"));
+        assert!(attestations.matches("/eth2/01020304/beacon_attestation_63/ssz_snappy"));
+        assert!(!attestations.matches("/eth2/00000000/beacon_attestation_0/ssz"));
+        assert!(!attestations.matches("/eth2/00000000/beacon_block/ssz_snappy"));
+
+        let namespace = TopicPattern::new("/mothra/*".into()).unwrap();
+        assert!(namespace.matches("/mothra/"));
+        assert!(namespace.matches("/mothra/blocks/v1"));
+        assert!(!namespace.matches("/eth2/mothra/blocks"));
+
+        // the parts between wildcards must not overlap
+        let pattern = TopicPattern::new("ab*ba".into()).unwrap();
+        assert!(pattern.matches("abba"));
+        assert!(!pattern.matches("aba"));
+
+        assert!(TopicPattern::new("/mothra/blocks".into()).is_err());
+        assert!(TopicPattern::is_pattern("*"));
+        assert!(TopicPattern::new("*".into()).unwrap().matches(""));
+    }
+
+    #[test]
+    fn test_with_fork_digest() {
+        let topic = GossipTopic::new("/eth2/00000000/beacon_block/ssz_snappy".into());
+        assert_eq!(
+            topic.with_fork_digest([0xb5, 0x30, 0x3f, 0x2a]).unwrap(),
+            GossipTopic::new("/eth2/b5303f2a/beacon_block/ssz_snappy".into())
+        );
+    }
+}
@@ -1,9 +1,28 @@
-use clap::{App, AppSettings, Arg, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
 pub fn cli_app<'a, 'b>() -> App<'a, 'b> {
     App::new("mothra")
     .version(clap::crate_version!())
     .about("P2P networking component.")
+    .arg(
+        Arg::with_name("config")
+            .long("config")
+            .value_name("FILE")
+            .help("A TOML (.toml) or YAML (.yaml, .yml) network configuration file. Flags given on the command line override its values.")
+            .takes_value(true)
+    )
+    .subcommand(
+        SubCommand::with_name("dump-config")
+            .about("Prints the effective network configuration, in a format accepted by --config with the keys of private topics redacted, and exits.")
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .value_name("FORMAT")
+                    .possible_values(&["toml", "yaml"])
+                    .default_value("toml")
+                    .takes_value(true),
+            ),
+    )
     .arg(
         Arg::with_name("datadir")
             .long("datadir")
@@ -48,6 +67,160 @@ pub fn cli_app<'a, 'b>() -> App<'a, 'b> {
             .default_value("50")
             .takes_value(true),
     )
+    .arg(
+        Arg::with_name("max-peers-per-ip")
+            .long("max-peers-per-ip")
+            .value_name("COUNT")
+            .help("The maximum number of inbound peers connected from the same IP address. 0 disables the limit.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("max-peers-per-subnet")
+            .long("max-peers-per-subnet")
+            .value_name("COUNT")
+            .help("The maximum number of inbound peers connected from the same /24 (IPv4) or /64 (IPv6) network. 0 disables the limit.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("http-address")
+            .long("http-address")
+            .value_name("ADDRESS")
+            .help("Serves the node's state as JSON over HTTP on the given address (e.g. 127.0.0.1:5052). Disabled when not set.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("ws-address")
+            .long("ws-address")
+            .value_name("ADDRESS")
+            .help("Serves a JSON-RPC API over WebSocket on the given address (e.g. 127.0.0.1:5053) to publish, subscribe, send RPC messages and dial peers. Disabled when not set.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("gossip-mesh-n")
+            .long("gossip-mesh-n")
+            .value_name("COUNT")
+            .help("Target number of peers in the mesh of a gossipsub topic (D).")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("gossip-mesh-n-low")
+            .long("gossip-mesh-n-low")
+            .value_name("COUNT")
+            .help("Minimum number of peers in a gossipsub mesh before more are grafted (D_low).")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("gossip-mesh-n-high")
+            .long("gossip-mesh-n-high")
+            .value_name("COUNT")
+            .help("Maximum number of peers in a gossipsub mesh before some are pruned (D_high).")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("gossip-lazy")
+            .long("gossip-lazy")
+            .value_name("COUNT")
+            .help("Number of peers outside the mesh that gossip is emitted to (D_lazy).")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("gossip-history-length")
+            .long("gossip-history-length")
+            .value_name("HEARTBEATS")
+            .help("Number of heartbeats gossipsub messages are kept in the message cache for.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("gossip-history-gossip")
+            .long("gossip-history-gossip")
+            .value_name("HEARTBEATS")
+            .help("Number of heartbeats of the message cache advertised in gossip.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("gossip-fanout-ttl")
+            .long("gossip-fanout-ttl")
+            .value_name("MILLISECONDS")
+            .help("How long a fanout topic is kept after the last message published on it.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("gossip-duplicate-cache-time")
+            .long("gossip-duplicate-cache-time")
+            .value_name("MILLISECONDS")
+            .help("How long gossipsub message ids are remembered to drop duplicates.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("gossip-heartbeat-interval")
+            .long("gossip-heartbeat-interval")
+            .value_name("MILLISECONDS")
+            .help("Time between gossipsub heartbeats.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("gossip-message-id")
+            .long("gossip-message-id")
+            .value_name("STRATEGY")
+            .help("How gossipsub message ids are computed: a hash of the data, of the topic and the data, or the eth2 snappy-aware rule.")
+            .possible_values(&["data_hash", "topic_data_hash", "eth2_snappy"])
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("gossip-signing-policy")
+            .long("gossip-signing-policy")
+            .value_name("POLICY")
+            .help("Whether gossip messages are anonymous, signed by their author, or must be signed by their author to be accepted. The author of signed messages is delivered with them.")
+            .possible_values(&["anonymous", "signed", "strict"])
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("disable-peer-scoring")
+            .long("disable-peer-scoring")
+            .help("Disables the gossip peer scoring. The scoring parameters and thresholds can be set in the config file.")
+            .takes_value(false),
+    )
+    .arg(
+        Arg::with_name("propagation-percentage")
+            .long("propagation-percentage")
+            .value_name("PERCENT")
+            .help("Testing only: the percentage of published and propagated gossip messages that are sent, the others are dropped.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("propagation-delay")
+            .long("propagation-delay")
+            .value_name("MILLISECONDS")
+            .help("Testing only: delays published and propagated gossip messages by a random duration of up to this many milliseconds.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("propagation-seed")
+            .long("propagation-seed")
+            .value_name("SEED")
+            .help("Seeds the random decisions of --propagation-percentage and --propagation-delay so that runs are reproducible.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("capture-traffic")
+            .long("capture-traffic")
+            .help("Records all gossip and RPC messages to capture files in the network directory.")
+            .takes_value(false),
+    )
+    .arg(
+        Arg::with_name("capture-file-size")
+            .long("capture-file-size")
+            .value_name("MEGABYTES")
+            .help("The size in megabytes after which a new capture file is started.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("metrics-address")
+            .long("metrics-address")
+            .value_name("ADDRESS")
+            .help("Serves prometheus metrics over HTTP on the given address (e.g. 127.0.0.1:5054). Disabled when not set.")
+            .takes_value(true),
+    )
     .arg(
         Arg::with_name("boot-nodes")
             .long("boot-nodes")
@@ -67,7 +240,14 @@ pub fn cli_app<'a, 'b>() -> App<'a, 'b> {
         Arg::with_name("topics")
             .long("topics")
             .value_name("STRING")
-            .help("One or more comma-delimited gossipsub topics to subscribe to.")
+            .help("One or more comma-delimited gossipsub topics to subscribe to. Topics containing * are patterns, such as /eth2/*/beacon_attestation_*/ssz_snappy, that subscribe to the matching topics peers subscribe to.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("max-pattern-topics")
+            .long("max-pattern-topics")
+            .value_name("COUNT")
+            .help("The maximum number of topics a topic pattern subscribes to.")
             .takes_value(true),
     )
         .arg(
@@ -77,6 +257,20 @@ pub fn cli_app<'a, 'b>() -> App<'a, 'b> {
             .help("One or more comma-delimited multiaddrs to manually connect to a libp2p peer without an ENR.")
             .takes_value(true),
         )
+    .arg(
+        Arg::with_name("allow-list")
+            .long("allow-list")
+            .value_name("PEER-OR-CIDR-LIST")
+            .help("One or more comma-delimited peer ids or CIDR ranges (e.g. 10.0.0.0/8). When set, only matching peers may connect.")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("deny-list")
+            .long("deny-list")
+            .value_name("PEER-OR-CIDR-LIST")
+            .help("One or more comma-delimited peer ids or CIDR ranges (e.g. 10.0.0.0/8) that may never connect.")
+            .takes_value(true),
+    )
     .arg(
         Arg::with_name("debug-level")
             .long("debug-level")
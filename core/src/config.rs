@@ -1,6 +1,10 @@
 use clap::ArgMatches;
-use network::{unused_port, Enr, Multiaddr, NetworkConfig, DEFAULT_CLIENT_NAME};
-use std::path::PathBuf;
+use network::{unused_port, Enr, FilterEntry, Multiaddr, NetworkConfig, DEFAULT_CLIENT_NAME};
+use serde_json::Value;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 pub const DEFAULT_DEBUG_LEVEL: &str = "info";
 
@@ -54,12 +58,25 @@ impl Config {
         config
     }
     pub fn apply_cli_args(&mut self, args: &ArgMatches) -> Result<(), String> {
+        let config_file = args.value_of("config");
+        if let Some(path) = config_file {
+            self.load_config_file(Path::new(path))?;
+        }
+        // defaulted flags must not override the config file
+        let arg_value = |name: &str| {
+            if config_file.is_some() && args.occurrences_of(name) == 0 {
+                None
+            } else {
+                args.value_of(name)
+            }
+        };
+
         // If a `datadir` has been specified, set the network dir to be inside it.
         if let Some(dir) = args.value_of("datadir") {
             self.network_config.network_dir = PathBuf::from(dir).join("network");
         };
 
-        if let Some(listen_address_str) = args.value_of("listen-address") {
+        if let Some(listen_address_str) = arg_value("listen-address") {
             let listen_address = listen_address_str
                 .parse()
                 .map_err(|_| format!("Invalid listen address: {:?}", listen_address_str))?;
@@ -67,13 +84,107 @@ impl Config {
             self.network_config.enr_address = Some(listen_address);
         }
 
-        if let Some(max_peers_str) = args.value_of("maxpeers") {
+        if let Some(max_peers_str) = arg_value("maxpeers") {
             self.network_config.max_peers = max_peers_str
                 .parse::<usize>()
                 .map_err(|_| format!("Invalid number of max peers: {}", max_peers_str))?;
         }
 
-        if let Some(port_str) = args.value_of("port") {
+        if let Some(max_peers_per_ip_str) = args.value_of("max-peers-per-ip") {
+            self.network_config.max_peers_per_ip = max_peers_per_ip_str
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid max peers per IP: {}", max_peers_per_ip_str))?;
+        }
+
+        if let Some(max_peers_per_subnet_str) = args.value_of("max-peers-per-subnet") {
+            self.network_config.max_peers_per_subnet =
+                max_peers_per_subnet_str.parse::<usize>().map_err(|_| {
+                    format!("Invalid max peers per subnet: {}", max_peers_per_subnet_str)
+                })?;
+        }
+
+        if let Some(http_address_str) = args.value_of("http-address") {
+            let http_address = http_address_str
+                .parse::<SocketAddr>()
+                .map_err(|_| format!("Invalid HTTP API address: {}", http_address_str))?;
+            self.network_config.http_address = Some(http_address);
+        }
+
+        if let Some(ws_address_str) = args.value_of("ws-address") {
+            let ws_address = ws_address_str
+                .parse::<SocketAddr>()
+                .map_err(|_| format!("Invalid WebSocket API address: {}", ws_address_str))?;
+            self.network_config.ws_address = Some(ws_address);
+        }
+
+        let gossipsub = &mut self.network_config.gossipsub;
+        if let Some(mesh_n) = parse_arg(args, "gossip-mesh-n")? {
+            gossipsub.mesh_n = mesh_n;
+        }
+        if let Some(mesh_n_low) = parse_arg(args, "gossip-mesh-n-low")? {
+            gossipsub.mesh_n_low = mesh_n_low;
+        }
+        if let Some(mesh_n_high) = parse_arg(args, "gossip-mesh-n-high")? {
+            gossipsub.mesh_n_high = mesh_n_high;
+        }
+        if let Some(gossip_lazy) = parse_arg(args, "gossip-lazy")? {
+            gossipsub.gossip_lazy = gossip_lazy;
+        }
+        if let Some(history_length) = parse_arg(args, "gossip-history-length")? {
+            gossipsub.history_length = history_length;
+        }
+        if let Some(history_gossip) = parse_arg(args, "gossip-history-gossip")? {
+            gossipsub.history_gossip = history_gossip;
+        }
+        if let Some(fanout_ttl_ms) = parse_arg(args, "gossip-fanout-ttl")? {
+            gossipsub.fanout_ttl_ms = fanout_ttl_ms;
+        }
+        if let Some(duplicate_cache_time_ms) = parse_arg(args, "gossip-duplicate-cache-time")? {
+            gossipsub.duplicate_cache_time_ms = duplicate_cache_time_ms;
+        }
+        if let Some(heartbeat_interval_ms) = parse_arg(args, "gossip-heartbeat-interval")? {
+            gossipsub.heartbeat_interval_ms = heartbeat_interval_ms;
+        }
+        if let Some(message_id) = parse_arg(args, "gossip-message-id")? {
+            gossipsub.message_id = message_id;
+        }
+        if let Some(signing_policy) = parse_arg(args, "gossip-signing-policy")? {
+            gossipsub.signing_policy = signing_policy;
+        }
+
+        if args.is_present("disable-peer-scoring") {
+            self.network_config.disable_peer_scoring = true;
+        }
+
+        if let Some(propagation_percentage) = parse_arg(args, "propagation-percentage")? {
+            self.network_config.propagation_percentage = Some(propagation_percentage);
+        }
+        if let Some(propagation_delay_ms) = parse_arg(args, "propagation-delay")? {
+            self.network_config.propagation_delay_ms = propagation_delay_ms;
+        }
+        if let Some(propagation_seed) = parse_arg(args, "propagation-seed")? {
+            self.network_config.propagation_seed = Some(propagation_seed);
+        }
+
+        if args.is_present("capture-traffic") {
+            self.network_config.capture_traffic = true;
+        }
+
+        if let Some(capture_file_size_str) = args.value_of("capture-file-size") {
+            let megabytes = capture_file_size_str
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid capture file size: {}", capture_file_size_str))?;
+            self.network_config.capture_file_size = megabytes * 1024 * 1024;
+        }
+
+        if let Some(metrics_address_str) = args.value_of("metrics-address") {
+            let metrics_address = metrics_address_str
+                .parse::<SocketAddr>()
+                .map_err(|_| format!("Invalid metrics address: {}", metrics_address_str))?;
+            self.network_config.metrics_address = Some(metrics_address);
+        }
+
+        if let Some(port_str) = arg_value("port") {
             let port = port_str
                 .parse::<u16>()
                 .map_err(|_| format!("Invalid port: {}", port_str))?;
@@ -108,6 +219,20 @@ impl Config {
                 .collect::<Result<Vec<Multiaddr>, _>>()?;
         }
 
+        if let Some(allow_list_str) = args.value_of("allow-list") {
+            self.network_config.allow_list = allow_list_str
+                .split(',')
+                .map(|entry| entry.parse())
+                .collect::<Result<Vec<FilterEntry>, _>>()?;
+        }
+
+        if let Some(deny_list_str) = args.value_of("deny-list") {
+            self.network_config.deny_list = deny_list_str
+                .split(',')
+                .map(|entry| entry.parse())
+                .collect::<Result<Vec<FilterEntry>, _>>()?;
+        }
+
         if let Some(enr_address_str) = args.value_of("enr-address") {
             self.network_config.enr_address = Some(
                 enr_address_str
@@ -133,14 +258,17 @@ impl Config {
         }
 
         if args.is_present("disable_enr_auto_update") {
-            self.network_config.discv5_config.enr_update = false;
+            self.network_config.discovery.enr_update = false;
         }
 
         if let Some(topics_str) = args.value_of("topics") {
             self.network_config.topics = topics_str.split(',').map(|s| s.into()).collect();
         }
+        if let Some(max_pattern_topics) = parse_arg(args, "max-pattern-topics")? {
+            self.network_config.max_pattern_topics = max_pattern_topics;
+        }
 
-        if let Some(debug_level_str) = args.value_of("debug-level") {
+        if let Some(debug_level_str) = arg_value("debug-level") {
             self.debug_level = debug_level_str
                 .parse()
                 .map_err(|_| format!("Invalid debug-level: {:?}", debug_level_str))?;
@@ -160,6 +288,135 @@ impl Config {
             self.network_config.enr_tcp_port = Some(self.network_config.libp2p_port);
             self.network_config.enr_udp_port = Some(self.network_config.discovery_port);
         }
+
+        self.network_config.gossipsub.validate()?;
+        self.network_config.peer_score_params.validate()?;
+        self.network_config.peer_score_thresholds.validate()?;
+        if let Some((topic, _)) = self
+            .network_config
+            .message_ttls
+            .iter()
+            .find(|(_, ttl_ms)| **ttl_ms == 0)
+        {
+            return Err(format!(
+                "The message time to live of topic {} must be positive",
+                topic
+            ));
+        }
+        if let Some(percentage) = self.network_config.propagation_percentage {
+            if percentage > 100 {
+                return Err(format!("Invalid propagation percentage: {}", percentage));
+            }
+        }
+        self.network_config.update_protocol_configs();
         Ok(())
     }
+
+    /// Loads a TOML or YAML network configuration file. Values missing from the file are kept.
+    fn load_config_file(&mut self, path: &Path) -> Result<(), String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read config file {:?}: {}", path, e))?;
+        let file_config: Value = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents)
+                .map_err(|e| format!("Invalid config file {:?}: {}", path, e))?,
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)
+                .map_err(|e| format!("Invalid config file {:?}: {}", path, e))?,
+            _ => {
+                return Err(format!(
+                    "Unknown config file format {:?}, expected .toml, .yaml or .yml",
+                    path
+                ))
+            }
+        };
+
+        // merge the file over the current values, which may have been set by the application
+        let mut config = serde_json::to_value(&self.network_config)
+            .map_err(|e| format!("Could not serialize the network config: {}", e))?;
+        merge(&mut config, file_config);
+        let mut network_config: NetworkConfig = serde_json::from_value(config)
+            .map_err(|e| format!("Invalid config file {:?}: {}", path, e))?;
+        network_config.secret_key_hex = self.network_config.secret_key_hex.take();
+        self.network_config = network_config;
+        Ok(())
+    }
+
+    /// Returns the network configuration serialized in the requested format if the `dump-config`
+    /// subcommand was given, in which case the caller prints it instead of starting the node.
+    pub fn requested_dump(&self, args: &ArgMatches) -> Option<Result<String, String>> {
+        args.subcommand_matches("dump-config")
+            .map(|dump_args| self.dump(dump_args.value_of("format").unwrap_or("toml")))
+    }
+
+    /// Serializes the network configuration as TOML or YAML, with the keys of private topics
+    /// redacted.
+    pub fn dump(&self, format: &str) -> Result<String, String> {
+        let mut network_config = self.network_config.clone();
+        for key in network_config.topic_keys.values_mut().flatten() {
+            key.key_hex = "<redacted>".into();
+        }
+        match format {
+            // convert to a toml value first so that tables are written after plain values
+            "toml" => toml::Value::try_from(&network_config)
+                .and_then(|value| toml::to_string_pretty(&value))
+                .map_err(|e| format!("Could not serialize the network config: {}", e)),
+            "yaml" => serde_yaml::to_string(&network_config)
+                .map_err(|e| format!("Could not serialize the network config: {}", e)),
+            _ => Err(format!("Unknown config format: {}", format)),
+        }
+    }
+}
+
+/// Parses the value of a CLI argument, if it is present.
+fn parse_arg<T: FromStr>(args: &ArgMatches, name: &str) -> Result<Option<T>, String> {
+    args.value_of(name)
+        .map(|value| {
+            value
+                .parse::<T>()
+                .map_err(|_| format!("Invalid {}: {}", name, value))
+        })
+        .transpose()
+}
+
+/// Recursively overrides the values of `base` with those of `overrides`.
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::TopicKey;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_config_file_keeps_topic_keys() {
+        let mut config = Config::default();
+        let keys = vec![TopicKey {
+            id: 1,
+            key_hex: "07".repeat(32),
+        }];
+        config
+            .network_config
+            .topic_keys
+            .insert("/mothra/private".into(), keys.clone());
+
+        let dir = TempDir::new("mothra-config").unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "max_peers = 10\n").unwrap();
+        config.load_config_file(&path).unwrap();
+        assert_eq!(config.network_config.max_peers, 10);
+        assert_eq!(config.network_config.topic_keys["/mothra/private"], keys);
+
+        // only dumps redact the keys
+        let dump = config.dump("toml").unwrap();
+        assert!(!dump.contains(&keys[0].key_hex));
+        assert_eq!(config.network_config.topic_keys["/mothra/private"], keys);
+    }
 }
@@ -0,0 +1,148 @@
+//! Drops and delays published and propagated gossip messages to test applications under lossy
+//! network conditions.
+//!
+//! The decisions are drawn from a seedable random number generator, so that a simulation run with
+//! the same seed and the same sequence of messages makes the same decisions.
+use network::metrics;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use slog::debug;
+use std::time::Duration;
+
+/// What happens to a message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// The message is sent right away.
+    Pass,
+    /// The message is not sent.
+    Drop,
+    /// The message is sent after the given delay.
+    Delay(Duration),
+}
+
+/// Decides which messages are dropped or delayed.
+pub struct PropagationFaults {
+    /// The percentage of messages that are sent, if messages are dropped.
+    propagation_percentage: Option<u8>,
+    /// The maximum delay of the messages that are sent.
+    max_delay: Duration,
+    rng: StdRng,
+    log: slog::Logger,
+}
+
+impl PropagationFaults {
+    pub fn new(
+        propagation_percentage: Option<u8>,
+        max_delay: Duration,
+        seed: Option<u64>,
+        log: slog::Logger,
+    ) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        PropagationFaults {
+            propagation_percentage,
+            max_delay,
+            rng,
+            log,
+        }
+    }
+
+    /// Whether any message can be dropped or delayed.
+    pub fn enabled(&self) -> bool {
+        self.propagation_percentage
+            .map_or(false, |percentage| percentage < 100)
+            || self.max_delay > Duration::from_millis(0)
+    }
+
+    /// Decides what happens to a message of the given kind, `publish` or `propagate`.
+    pub fn decide(&mut self, kind: &str) -> Fault {
+        if !self.enabled() {
+            return Fault::Pass;
+        }
+
+        let fault = match self.propagation_percentage {
+            Some(percentage) if self.rng.gen_range(0, 100) >= percentage => Fault::Drop,
+            _ => {
+                let max_delay_ms = self.max_delay.as_millis() as u64;
+                match self.rng.gen_range(0, max_delay_ms + 1) {
+                    0 => Fault::Pass,
+                    delay_ms => Fault::Delay(Duration::from_millis(delay_ms)),
+                }
+            }
+        };
+
+        match fault {
+            Fault::Pass => {}
+            Fault::Drop => {
+                debug!(self.log, "Dropping gossip message"; "kind" => kind);
+                metrics::inc_counter_vec(&metrics::GOSSIP_FAULTS_INJECTED, &[kind, "drop"]);
+            }
+            Fault::Delay(delay) => {
+                debug!(self.log, "Delaying gossip message"; "kind" => kind,
+                    "delay" => format!("{:?}", delay));
+                metrics::inc_counter_vec(&metrics::GOSSIP_FAULTS_INJECTED, &[kind, "delay"]);
+            }
+        }
+        fault
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::{o, Discard};
+
+    fn new_faults(percentage: Option<u8>, max_delay_ms: u64, seed: u64) -> PropagationFaults {
+        PropagationFaults::new(
+            percentage,
+            Duration::from_millis(max_delay_ms),
+            Some(seed),
+            slog::Logger::root(Discard, o!()),
+        )
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut faults = new_faults(None, 0, 1);
+        assert!(!faults.enabled());
+        assert!((0..100).all(|_| faults.decide("publish") == Fault::Pass));
+
+        let mut faults = new_faults(Some(100), 0, 1);
+        assert!(!faults.enabled());
+        assert!((0..100).all(|_| faults.decide("publish") == Fault::Pass));
+    }
+
+    #[test]
+    fn test_drop_everything() {
+        let mut faults = new_faults(Some(0), 100, 1);
+        assert!((0..100).all(|_| faults.decide("propagate") == Fault::Drop));
+    }
+
+    #[test]
+    fn test_delays_are_bounded() {
+        let mut faults = new_faults(None, 50, 1);
+        for _ in 0..100 {
+            match faults.decide("publish") {
+                Fault::Pass => {}
+                Fault::Delay(delay) => assert!(delay <= Duration::from_millis(50)),
+                Fault::Drop => panic!("messages must not be dropped"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_seeded_decisions_are_reproducible() {
+        let decisions = |seed| {
+            let mut faults = new_faults(Some(50), 20, seed);
+            (0..100)
+                .map(|_| faults.decide("publish"))
+                .collect::<Vec<_>>()
+        };
+        let first_run = decisions(7);
+        assert_eq!(first_run, decisions(7));
+        assert!(first_run.contains(&Fault::Drop));
+        assert!(first_run.iter().any(|fault| *fault != Fault::Drop));
+    }
+}
@@ -0,0 +1,127 @@
+//! Local HTTP API exposing the state of the node as JSON.
+//!
+//! Endpoints:
+//! - `/node/identity`: the local peer id, ENR and listening addresses.
+//! - `/node/enr`: the local ENR, base64 encoded.
+//! - `/node/peers`: all known peers.
+//! - `/node/peers/{peer_id}`: a single known peer.
+//! - `/node/topics`: the subscribed gossipsub topics.
+use crate::error;
+use crate::http_server::{self, status_response};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use network::{NetworkGlobals, PeerId, PeerInfo, TaskExecutor};
+use serde_derive::Serialize;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// The identity of the local node.
+#[derive(Serialize)]
+struct Identity {
+    peer_id: String,
+    enr: String,
+    listen_addresses: Vec<String>,
+    tcp_port: u16,
+    udp_port: u16,
+}
+
+/// A known peer and what the `PeerDB` knows about it.
+#[derive(Serialize)]
+struct Peer<'a> {
+    peer_id: String,
+    peer_info: &'a PeerInfo,
+}
+
+/// Starts the API server on `listen_address`. The server stops when the executor exits.
+pub fn serve(
+    executor: &TaskExecutor,
+    listen_address: SocketAddr,
+    network_globals: Arc<NetworkGlobals>,
+    log: slog::Logger,
+) -> error::Result<()> {
+    http_server::serve(
+        executor,
+        listen_address,
+        "HTTP API",
+        move |request| handle_request(request, &network_globals),
+        log,
+    )
+}
+
+fn handle_request(request: Request<Body>, network_globals: &NetworkGlobals) -> Response<Body> {
+    if request.method() != Method::GET {
+        return status_response(StatusCode::METHOD_NOT_ALLOWED, "Only GET is supported");
+    }
+
+    let segments: Vec<&str> = request
+        .uri()
+        .path()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+
+    match segments.as_slice() {
+        ["node", "identity"] => json_response(&Identity {
+            peer_id: network_globals.local_peer_id().to_string(),
+            enr: network_globals.local_enr().to_base64(),
+            listen_addresses: network_globals
+                .listen_multiaddrs()
+                .iter()
+                .map(|addr| addr.to_string())
+                .collect(),
+            tcp_port: network_globals.listen_port_tcp(),
+            udp_port: network_globals.listen_port_udp(),
+        }),
+        ["node", "enr"] => json_response(&network_globals.local_enr().to_base64()),
+        ["node", "peers"] => {
+            let peers = network_globals.peers.read();
+            let peers: Vec<Peer> = peers
+                .peers()
+                .map(|(peer_id, peer_info)| Peer {
+                    peer_id: peer_id.to_string(),
+                    peer_info,
+                })
+                .collect();
+            json_response(&peers)
+        }
+        ["node", "peers", peer_id] => {
+            let peer_id = match PeerId::from_str(peer_id) {
+                Ok(peer_id) => peer_id,
+                Err(_) => return status_response(StatusCode::BAD_REQUEST, "Invalid peer id"),
+            };
+            let peers = network_globals.peers.read();
+            match peers.peer_info(&peer_id) {
+                Some(peer_info) => json_response(&Peer {
+                    peer_id: peer_id.to_string(),
+                    peer_info,
+                }),
+                None => status_response(StatusCode::NOT_FOUND, "Unknown peer"),
+            }
+        }
+        ["node", "topics"] => {
+            let topics: Vec<String> = network_globals
+                .gossipsub_subscriptions
+                .read()
+                .iter()
+                .map(|topic| topic.clone().into())
+                .collect();
+            json_response(&topics)
+        }
+        _ => status_response(StatusCode::NOT_FOUND, "Not found"),
+    }
+}
+
+fn json_response<T: serde::Serialize>(body: &T) -> Response<Body> {
+    match serde_json::to_vec(body) {
+        Ok(body) => Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap_or_else(|_| Response::new(Body::empty())),
+        Err(e) => status_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Unable to serialize response: {}", e),
+        ),
+    }
+}
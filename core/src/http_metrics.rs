@@ -0,0 +1,42 @@
+//! HTTP server exposing the network metrics to prometheus on `/metrics`.
+use crate::error;
+use crate::http_server::{self, status_response};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use network::{metrics, NetworkGlobals, TaskExecutor};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// The content type of the prometheus text format.
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Starts the metrics server on `listen_address`. The server stops when the executor exits.
+pub fn serve(
+    executor: &TaskExecutor,
+    listen_address: SocketAddr,
+    network_globals: Arc<NetworkGlobals>,
+    log: slog::Logger,
+) -> error::Result<()> {
+    http_server::serve(
+        executor,
+        listen_address,
+        "Metrics server",
+        move |request| handle_request(request, &network_globals),
+        log,
+    )
+}
+
+fn handle_request(request: Request<Body>, network_globals: &NetworkGlobals) -> Response<Body> {
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        return status_response(StatusCode::NOT_FOUND, "Not found");
+    }
+
+    match metrics::gather(network_globals) {
+        Ok(body) => Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, METRICS_CONTENT_TYPE)
+            .body(Body::from(body))
+            .unwrap_or_else(|_| Response::new(Body::empty())),
+        Err(e) => status_response(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+}
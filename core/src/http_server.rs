@@ -0,0 +1,55 @@
+//! The HTTP server shared by the HTTP API and the metrics endpoint.
+use crate::error;
+use futures::prelude::*;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use network::TaskExecutor;
+use slog::{info, warn};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Starts a server answering the requests on `listen_address` with `handle_request`. The server
+/// is named `name` in logs and stops when the executor exits.
+pub fn serve<F>(
+    executor: &TaskExecutor,
+    listen_address: SocketAddr,
+    name: &'static str,
+    handle_request: F,
+    log: slog::Logger,
+) -> error::Result<()>
+where
+    F: Fn(Request<Body>) -> Response<Body> + Send + Sync + 'static,
+{
+    let handle_request = Arc::new(handle_request);
+    let make_service = make_service_fn(move |_| {
+        let handle_request = handle_request.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                future::ok::<_, Infallible>(handle_request(request))
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&listen_address)
+        .map_err(|e| format!("Unable to bind {} to {}: {}", name, listen_address, e))?
+        .serve(make_service);
+    info!(log, "{} started", name; "address" => format!("http://{}", server.local_addr()));
+
+    let exit = executor.exit();
+    let server_future = server.with_graceful_shutdown(exit).map(move |result| {
+        if let Err(e) = result {
+            warn!(log, "{} failed", name; "error" => e.to_string());
+        }
+    });
+    // hyper shuts down gracefully on exit
+    executor.spawn_without_exit(server_future, name);
+    Ok(())
+}
+
+/// A plain text response with the given status.
+pub fn status_response(status: StatusCode, message: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(message.to_string()));
+    *response.status_mut() = status;
+    response
+}
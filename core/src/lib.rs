@@ -1,7 +1,20 @@
 pub mod cli;
 pub mod config;
 pub mod error;
+mod faults;
+mod http_api;
+mod http_metrics;
+mod http_server;
 mod mothra;
-pub use crate::mothra::{gossip, rpc_request, rpc_response, Mothra, NetworkMessage, Subscriber};
+pub mod recorder;
+mod ws_api;
+pub use crate::mothra::{
+    add_filter_entry, ban, dial, disconnect, gossip, remove_filter_entry, rpc_error_response,
+    rpc_request, rpc_response, unban, validate_gossip, Mothra, NetworkMessage, Subscriber,
+};
 pub use cli::cli_app;
-pub use network::{rpc, NetworkGlobals, PeerId as MothraPeerId, Request, Response, TaskExecutor};
+pub use network::{
+    rpc, Eth2Kind, Eth2Topic, GossipEncoding, GossipTopic, GossipsubMessage, MessageId,
+    NetworkGlobals, PeerId as MothraPeerId, PublishError, Request, Response, TaskExecutor,
+    TopicPattern,
+};
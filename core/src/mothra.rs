@@ -1,39 +1,61 @@
-use crate::{config::Config, error};
+use crate::faults::{Fault, PropagationFaults};
+use crate::recorder::{Direction, RecordKind, Recorder};
+use crate::ws_api::{self, WsNotification};
+use crate::{config::Config, error, http_api, http_metrics};
 use futures::prelude::*;
 use futures::Stream;
 use network::Service as LibP2PService;
 use network::{
     rpc::{GoodbyeReason, RPCResponseErrorCode, RequestId, StatusMessage},
-    types::GossipKind,
-    BehaviourEvent, ConnectedPoint, GossipTopic, Libp2pEvent, MessageId, NetworkConfig,
-    NetworkGlobals, PeerId, PeerRequestId, Request, Response, Swarm, TaskExecutor,
+    BehaviourEvent, Client, ConnectedPoint, ConnectionDirection, ConnectionGate, Enr, FilterEntry,
+    FilterList, GossipEncoding, GossipTopic, Libp2pEvent, MessageId, Multiaddr, NetworkConfig,
+    NetworkGlobals, PeerId, PeerRequestId, PublishError, Request, Response, Swarm, TaskExecutor,
+    TopicPattern,
 };
 
 use clap::ArgMatches;
 use slog::{debug, info, o, trace, warn, Drain, Level, Logger};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc, oneshot};
 use parking_lot::RwLock;
 use std::sync::atomic::{AtomicU8,Ordering};
-
-/// The time in seconds that a peer will be banned and prevented from reconnecting.
-const BAN_PEER_TIMEOUT: u64 = 30;
+use std::time::Duration;
 
 pub type DiscoveredPeerType = fn(peer: String);
-pub type ReceiveGossipType = fn(message_id: String, peer_id: String, topic: String, data: Vec<u8>);
+pub type ReceiveGossipType = fn(message_id: String, peer_id: String, topics: Vec<String>, data: Vec<u8>);
 pub type ReceiveRpcType = fn(method: String, req_resp: u8, peer: String, data: Vec<u8>);
 
 pub trait Subscriber {
     fn init(&mut self, send: mpsc::UnboundedSender<NetworkMessage>, fork_id: Vec<u8>);
     fn discovered_peer(&self, peer: String);
-    fn receive_gossip(&self, message_id: String, sequence_number: u64, agent_string: String, peer_id: String, topic: String, data: Vec<u8>);
+    /// Delivers a gossip message with all the topics it was published on, as subscribed to.
+    /// `peer_id` is the peer that forwarded the message, `author` the peer that published it if
+    /// it signed it. The sequence number is chosen by the publisher but not signed, and a signed
+    /// message can be replayed once gossipsub forgot its id.
+    fn receive_gossip(&self, message_id: String, sequence_number: u64, agent_string: String, peer_id: String, author: Option<String>, topics: Vec<String>, data: Vec<u8>);
     fn receive_rpc(&self, method: String, req_resp: u8, peer: String, data: Vec<u8>);
+    /// Decides whether a connection with a peer is accepted. `direction` is 0 for inbound
+    /// connections and 1 for outbound connections, `agent_string` is empty if the peer is unknown.
+    ///
+    /// This is called once per connection, inbound or outbound, from the transport of the network
+    /// threads once the peer is authenticated and before any traffic is exchanged with it,
+    /// concurrently with the other callbacks. Discovered peers are dialed without asking first, so
+    /// their connections are decided here with the address actually dialed.
+    fn accept_connection(
+        &self,
+        _peer_id: String,
+        _address: String,
+        _direction: u8,
+        _agent_string: String,
+    ) -> bool {
+        true
+    }
 }
 
 /// Handles communication between calling code and the `libp2p_p2p` service.
 pub struct Mothra {
-    /// Reference to the client using Mothra
-    client: Box<dyn Subscriber + Send>,
+    /// Reference to the client using Mothra, shared with the connection gate.
+    client: Arc<dyn Subscriber + Send + Sync>,
     /// The underlying libp2p service that drives all the network interactions.
     libp2p: LibP2PService,
     /// The network receiver channel
@@ -42,8 +64,16 @@ pub struct Mothra {
     network_send: mpsc::UnboundedSender<NetworkMessage>,
     /// A collection of global variables, accessible outside of the network service.
     network_globals: Arc<NetworkGlobals>,
-    /// Probability of message propagation.
-    propagation_percentage: Option<u8>,
+    /// Records the traffic to capture files, if enabled.
+    recorder: Option<Recorder>,
+    /// Forwards received messages to the WebSocket API clients, if the API is enabled.
+    ws_notifications: Option<broadcast::Sender<WsNotification>>,
+    /// Drops and delays published and propagated messages, when testing.
+    faults: PropagationFaults,
+    /// Sends the delayed messages back to the network service once their delay elapsed.
+    delayed_send: mpsc::UnboundedSender<NetworkMessage>,
+    /// The delayed messages to send, which are not dropped or delayed again.
+    delayed_recv: mpsc::UnboundedReceiver<NetworkMessage>,
     // TODO: Make a struct that implements this functionality.
     // It should hold an array and a counter
     /// rpc requests
@@ -61,12 +91,32 @@ impl Mothra {
         meta_data: Vec<u8>,
         ping_data: Vec<u8>,
         executor: &TaskExecutor,
-        mut client: Box<dyn Subscriber + Send>,
+        mut client: Box<dyn Subscriber + Send + Sync>,
         log: slog::Logger,
     ) -> error::Result<(Arc<NetworkGlobals>, mpsc::UnboundedSender<NetworkMessage>)> {
         // build the network channel
         let (network_send, network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
         client.init(network_send.clone(), enr_fork_id.clone());
+        let client: Arc<dyn Subscriber + Send + Sync> = Arc::from(client);
+        // let the client decide which connections are accepted, without waiting for the network
+        // service to deliver its events
+        let gate_client = client.clone();
+        let connection_gate: ConnectionGate =
+            Arc::new(move |peer_id: &PeerId,
+                           address: &Multiaddr,
+                           direction: ConnectionDirection,
+                           peer_client: &Client| {
+                let direction = match direction {
+                    ConnectionDirection::Incoming => 0,
+                    ConnectionDirection::Outgoing => 1,
+                };
+                gate_client.accept_connection(
+                    peer_id.to_string(),
+                    address.to_string(),
+                    direction,
+                    peer_client.agent_string.clone().unwrap_or_default(),
+                )
+            });
         // Inject the executor into the discv5 network config.
         config.network_config.discv5_config.executor = Some(Box::new(executor.clone()));
         // launch libp2p Network
@@ -76,19 +126,93 @@ impl Mothra {
             enr_fork_id,
             meta_data,
             ping_data,
+            Some(connection_gate),
             &log.clone(),
         )?;
 
-        let mut subscribed_topics: Vec<GossipKind> = vec![];
-        for topic_kind in &config.network_config.topics {
-            if libp2p.swarm.subscribe_kind(topic_kind.clone()) {
-                subscribed_topics.push(topic_kind.clone());
+        let mut subscribed_topics: Vec<String> = vec![];
+        for topic in &config.network_config.topics {
+            if TopicPattern::is_pattern(topic) {
+                match TopicPattern::new(topic.clone()) {
+                    Ok(pattern) => {
+                        libp2p.swarm.subscribe_pattern(pattern);
+                        subscribed_topics.push(topic.clone());
+                    }
+                    Err(e) => warn!(log, "Invalid topic pattern"; "pattern" => topic, "error" => e),
+                }
+                continue;
+            }
+            let gossip_topic = GossipTopic::new(topic.clone());
+            if let Err(e) = gossip_topic.validate() {
+                warn!(log, "Invalid topic"; "topic" => topic, "error" => e);
+            } else if libp2p.swarm.subscribe(gossip_topic) {
+                subscribed_topics.push(topic.clone());
             } else {
-                warn!(log, "Could not subscribe to topic"; "topic" => format!("{}",topic_kind));
+                warn!(log, "Could not subscribe to topic"; "topic" => format!("{}",topic));
             }
         }
         info!(log, "Subscribed to topics"; "topics" => format!("{:?}", subscribed_topics));
 
+        if let Some(http_address) = config.network_config.http_address {
+            http_api::serve(
+                executor,
+                http_address,
+                network_globals.clone(),
+                log.new(o!("service" => "http_api")),
+            )?;
+        }
+
+        if let Some(metrics_address) = config.network_config.metrics_address {
+            http_metrics::serve(
+                executor,
+                metrics_address,
+                network_globals.clone(),
+                log.new(o!("service" => "http_metrics")),
+            )?;
+        }
+
+        let ws_notifications = match config.network_config.ws_address {
+            Some(ws_address) => {
+                let (notifications, _) = broadcast::channel(ws_api::NOTIFICATION_CAPACITY);
+                ws_api::serve(
+                    executor,
+                    ws_address,
+                    network_send.clone(),
+                    network_globals.clone(),
+                    notifications.clone(),
+                    log.new(o!("service" => "ws_api")),
+                )?;
+                Some(notifications)
+            }
+            None => None,
+        };
+
+        let recorder = if config.network_config.capture_traffic {
+            let recorder = Recorder::new(
+                config.network_config.network_dir.join("captures"),
+                config.network_config.capture_file_size,
+                config.network_config.gossipsub.max_transmit_size,
+                log.new(o!("service" => "recorder")),
+            )
+            .map_err(|e| format!("Unable to start the traffic recorder: {}", e))?;
+            Some(recorder)
+        } else {
+            None
+        };
+
+        let faults = PropagationFaults::new(
+            config.network_config.propagation_percentage,
+            Duration::from_millis(config.network_config.propagation_delay_ms),
+            config.network_config.propagation_seed,
+            log.new(o!("service" => "faults")),
+        );
+        if faults.enabled() {
+            warn!(log, "Dropping or delaying gossip messages for testing";
+                "propagation_percentage" => config.network_config.propagation_percentage,
+                "max_delay_ms" => config.network_config.propagation_delay_ms);
+        }
+        let (delayed_send, delayed_recv) = mpsc::unbounded_channel::<NetworkMessage>();
+
         // create & spawn the network service
         let network_service = Mothra {
             client,
@@ -96,7 +220,11 @@ impl Mothra {
             network_recv,
             network_send: network_send.clone(),
             network_globals: network_globals.clone(),
-            propagation_percentage: config.network_config.propagation_percentage,
+            recorder,
+            ws_notifications,
+            faults,
+            delayed_send,
+            delayed_recv,
             requests: RwLock::new([None; 256]),
             num_requests: AtomicU8::new(0),
             log: log.clone(),
@@ -118,6 +246,70 @@ impl Mothra {
         config.apply_cli_args(args).unwrap();
         config
     }
+
+    /// Records a message to the capture files, if recording is enabled. Gossip is recorded as
+    /// encoded for its topic, RPC payloads with the `Raw` encoding.
+    fn record(
+        &mut self,
+        direction: Direction,
+        peer_id: Option<&PeerId>,
+        kind: RecordKind,
+        data: &[u8],
+        encoding: GossipEncoding,
+    ) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            let peer_id = peer_id.map(|peer_id| peer_id.to_string());
+            recorder.record(direction, peer_id, kind, data, encoding);
+        }
+    }
+
+    /// Publishes a message and reports the result to the sender, if it waits for it.
+    fn publish(
+        &mut self,
+        topic: GossipTopic,
+        message: Vec<u8>,
+        result: Option<oneshot::Sender<Result<usize, PublishError>>>,
+    ) {
+        debug!(self.log, "Sending pubsub message"; "topic" => format!("{:?}", topic));
+        let publish_result = self.libp2p.swarm.publish(topic.clone(), message);
+        if let Err(e) = &publish_result {
+            warn!(self.log, "Could not publish message"; "topic" => topic.to_string(),
+                "error" => e.to_string());
+        }
+        if let Some(result) = result {
+            // the caller may not wait for the result
+            let _ = result.send(publish_result);
+        }
+    }
+
+    /// Propagates a received gossipsub message that the application validated.
+    fn propagate(&mut self, propagation_source: PeerId, message_id: MessageId) {
+        trace!(self.log, "Propagating gossipsub message";
+            "propagation_peer" => format!("{:?}", propagation_source),
+            "message_id" => message_id.to_string(),
+        );
+        self.libp2p
+            .swarm
+            .propagate_message(&propagation_source, message_id);
+    }
+
+    /// Sends a message back to the network service after a delay.
+    fn delay(&self, delay: Duration, message: NetworkMessage) {
+        let delayed_send = self.delayed_send.clone();
+        tokio::spawn(async move {
+            tokio::time::delay_for(delay).await;
+            // the network service may have stopped in the meantime
+            let _ = delayed_send.send(message);
+        });
+    }
+
+    /// Forwards a received message to the WebSocket API clients.
+    fn notify(&self, notification: WsNotification) {
+        if let Some(notifications) = &self.ws_notifications {
+            // an error only means that no client is connected
+            let _ = notifications.send(notification);
+        }
+    }
 }
 
 fn spawn_mothra(mut mothra: Mothra, executor: &TaskExecutor) -> error::Result<()> {
@@ -139,10 +331,14 @@ fn spawn_mothra(mut mothra: Mothra, executor: &TaskExecutor) -> error::Result<()
                     match message {
                         NetworkMessage::SendRequest{ peer_id, request, request_id } => {
                             debug!(mothra.log, "SendRequest to peer: {:?} request type: {:?}", peer_id, request);
+                            let (protocol, data) = request_parts(&request);
+                            mothra.record(Direction::Outbound, Some(&peer_id), RecordKind::RpcRequest { protocol: protocol.into(), request_index: None }, data, GossipEncoding::Raw);
                             mothra.libp2p.send_request(peer_id, request_id, request);
                         }
                         NetworkMessage::SendResponse{ peer_id, response, index } => {
                             debug!(mothra.log, "SendResponse to peer: {:?} response type: {:?}", peer_id, response);
+                            let (protocol, data) = response_parts(&response);
+                            mothra.record(Direction::Outbound, Some(&peer_id), RecordKind::RpcResponse { protocol: protocol.into() }, data, GossipEncoding::Raw);
                             
                             match mothra.requests.read()[index as usize] {
                                 Some(id) => {
@@ -160,47 +356,114 @@ fn spawn_mothra(mut mothra: Mothra, executor: &TaskExecutor) -> error::Result<()
                             mothra.requests.write()[index as usize] = None;
                             
 
+                        }
+                        NetworkMessage::SendErrorResponse{ peer_id, error, reason, index } => {
+                            debug!(mothra.log, "SendErrorResponse to peer: {:?} error: {:?} reason: {:?}", peer_id, error, reason);
+                            mothra.record(Direction::Outbound, Some(&peer_id), RecordKind::RpcErrorResponse { error: error.to_string() }, reason.as_bytes(), GossipEncoding::Raw);
+
+                            match mothra.requests.read()[index as usize] {
+                                Some(id) => {
+                                    // decrement the numrequests
+                                    mothra.num_requests.fetch_sub(1, Ordering::SeqCst);
+                                    // send error response to libp2p
+                                    mothra.libp2p.respond_with_error(peer_id, id, error, reason);
+                                },
+                                None => {
+                                    warn!(mothra.log, "Issue with match request/response ids");
+                                }
+                            }
+
+                            // zero out the old PeerRequestId
+                            mothra.requests.write()[index as usize] = None;
                         }
                         NetworkMessage::Propagate {
                             propagation_source,
                             message_id,
                         } => {
-                                trace!(mothra.log, "Propagating gossipsub message";
+                                match mothra.faults.decide("propagate") {
+                                    Fault::Pass => mothra.propagate(propagation_source, message_id),
+                                    Fault::Drop => {}
+                                    Fault::Delay(delay) => mothra.delay(delay, NetworkMessage::Propagate { propagation_source, message_id }),
+                                }
+                        }
+                        NetworkMessage::Reject {
+                            propagation_source,
+                            message_id,
+                        } => {
+                                trace!(mothra.log, "Rejecting gossipsub message";
                                     "propagation_peer" => format!("{:?}", propagation_source),
                                     "message_id" => message_id.to_string(),
                                 );
                                 mothra
                                     .libp2p
                                     .swarm
-                                    .propagate_message(&propagation_source, message_id);
+                                    .reject_message(&propagation_source, message_id);
                         }
-                        NetworkMessage::Publish { topic, message } => {
-                                debug!(
-                                    mothra.log,
-                                    "Sending pubsub message";
-                                    "topic" => format!("{:?}", topic)
-                                );
-                                mothra.libp2p.swarm.publish(topic, message);
+                        NetworkMessage::Publish { topic, message, result } => {
+                                match mothra.faults.decide("publish") {
+                                    Fault::Pass => mothra.publish(topic, message, result),
+                                    Fault::Drop => {
+                                        // the message reached no peer
+                                        if let Some(result) = result {
+                                            let _ = result.send(Ok(0));
+                                        }
+                                    }
+                                    Fault::Delay(delay) => mothra.delay(delay, NetworkMessage::Publish { topic, message, result }),
+                                }
                         }
-                        NetworkMessage::Disconnect { peer_id } => {
-                            mothra.libp2p.disconnect_and_ban_peer(
-                                peer_id,
-                                std::time::Duration::from_secs(BAN_PEER_TIMEOUT),
-                            );
+                        NetworkMessage::Dial { address } => {
+                            mothra.libp2p.dial(address);
+                        }
+                        NetworkMessage::DialEnr { enr } => {
+                            mothra.libp2p.dial_enr(enr);
+                        }
+                        NetworkMessage::Disconnect { peer_id, reason } => {
+                            mothra.libp2p.disconnect_peer(peer_id, reason);
+                        }
+                        NetworkMessage::Ban { peer_id, duration } => {
+                            mothra.libp2p.disconnect_and_ban_peer(peer_id, duration);
+                        }
+                        NetworkMessage::Unban { peer_id } => {
+                            mothra.libp2p.unban_peer(peer_id);
+                        }
+                        NetworkMessage::AddFilterEntry { list, entry } => {
+                            mothra.libp2p.add_filter_entry(list, entry);
+                        }
+                        NetworkMessage::RemoveFilterEntry { list, entry } => {
+                            mothra.libp2p.remove_filter_entry(list, entry);
                         }
                         NetworkMessage::Subscribe { subscriptions } => {
-                            let mut subscribed_topics: Vec<GossipKind> = vec![];
-                            for topic_kind in subscriptions {
-                                if mothra.libp2p.swarm.subscribe_kind(topic_kind.clone().into()) {
-                                    subscribed_topics.push(topic_kind.clone().into());
+                            let mut subscribed_topics: Vec<String> = vec![];
+                            for topic in subscriptions {
+                                if let Err(e) = topic.validate() {
+                                    warn!(mothra.log, "Invalid topic"; "topic" => topic.to_string(), "error" => e);
+                                } else if mothra.libp2p.swarm.subscribe(topic.clone()) {
+                                    subscribed_topics.push(topic.into());
                                 } else {
-                                    warn!(mothra.log, "Could not subscribe to topic"; "topic" => format!("{:?}",topic_kind));
+                                    warn!(mothra.log, "Could not subscribe to topic"; "topic" => format!("{:?}",topic));
                                 }
                             }
                             info!(mothra.log, "Subscribed to topics"; "topics" => format!("{:?}", subscribed_topics));
                         }
+                        NetworkMessage::SubscribePattern { pattern } => {
+                                info!(mothra.log, "Subscribed to topic pattern"; "pattern" => pattern.to_string());
+                                mothra.libp2p.swarm.subscribe_pattern(pattern);
+                        }
+                        NetworkMessage::UnsubscribePattern { pattern } => {
+                                if mothra.libp2p.swarm.unsubscribe_pattern(&pattern) {
+                                    info!(mothra.log, "Unsubscribed from topic pattern"; "pattern" => pattern.to_string());
+                                }
+                        }
                     }
                 } // end mothra.network_recv.recv()
+                // handle a delayed message, whose fault was already decided
+                Some(message) = mothra.delayed_recv.recv() => {
+                    match message {
+                        NetworkMessage::Publish { topic, message, result } => mothra.publish(topic, message, result),
+                        NetworkMessage::Propagate { propagation_source, message_id } => mothra.propagate(propagation_source, message_id),
+                        _ => {}
+                    }
+                }
                 libp2p_event = mothra.libp2p.next_event() => {
                     // poll the swarm
                     match libp2p_event {
@@ -208,15 +471,28 @@ fn spawn_mothra(mut mothra: Mothra, executor: &TaskExecutor) -> error::Result<()
                             BehaviourEvent::RequestReceived{peer_id, id, request} => {
                                 debug!(mothra.log, "Mothra {:?} received from: {:?} id: {:?}", request, peer_id, id);
                                 let index = mothra.num_requests.load(Ordering::SeqCst);
+                                let (protocol, data) = request_parts(&request);
+                                mothra.record(Direction::Inbound, Some(&peer_id), RecordKind::RpcRequest { protocol: protocol.into(), request_index: Some(index) }, data, GossipEncoding::Raw);
                                 // Save the PeerRequestId
                                 mothra.requests.write()[index as usize] = Some(id);
                                 // incrememnt the variable that indicates the total number of indexes
                                 mothra.num_requests.fetch_add(1, Ordering::SeqCst);
+                                if let Request::Status(data) = &request {
+                                    mothra.notify(WsNotification::Rpc {
+                                        method: "Status".to_string(),
+                                        req_resp: 1,
+                                        peer_id: peer_id.to_string(),
+                                        request_index: Some(index),
+                                        data: data.clone(),
+                                    });
+                                }
                                 // Call out to bindings to encode and store the index of the PeerRequestId
                                 mothra.client.receive_rpc("Status".to_string(), 1, peer_id.to_string(), vec![index]);
                             }
                             BehaviourEvent::ResponseReceived{peer_id, id, response} => {
                                 debug!(mothra.log, "{:?} received from: {:?}", peer_id, response);
+                                let (protocol, data) = response_parts(&response);
+                                mothra.record(Direction::Inbound, Some(&peer_id), RecordKind::RpcResponse { protocol: protocol.into() }, data, GossipEncoding::Raw);
                             }
                             BehaviourEvent::RPCFailed{id, peer_id, error} => {
                                 debug!(mothra.log, "RPC request to: {:?} failed. error: {:?}", peer_id, error);
@@ -230,15 +506,40 @@ fn spawn_mothra(mut mothra: Mothra, executor: &TaskExecutor) -> error::Result<()
                                 sequence_number,
                                 agent_string,
                                 source,
+                                author,
                                 topics,
-                                message
+                                message,
+                                raw
                             } => {
-                                debug!(mothra.log, "Gossip message received from: {:?} topic: {:?}", source, topics);
-                                mothra.client.receive_gossip(id.to_string(), sequence_number, agent_string, source.to_string(), topics[0].to_string(), message.clone());
+                                debug!(mothra.log, "Gossip message received from: {:?} topics: {:?}", source, topics);
+                                let topics: Vec<String> = topics.into_iter().map(|topic| topic.into()).collect();
+                                let author = author.map(|author| author.to_string());
+                                // captures keep a single topic, the one the message is decoded with
+                                if let Some(raw) = raw {
+                                    let topic = topics.first().cloned().unwrap_or_default();
+                                    let encoding = GossipEncoding::from_topic(&topic);
+                                    mothra.record(Direction::Inbound, Some(&source), RecordKind::Gossip { topic, message_id: Some(id.to_string()), sequence_number: Some(sequence_number) }, &raw, encoding);
+                                }
+                                mothra.notify(WsNotification::Gossip {
+                                    message_id: id.to_string(),
+                                    sequence_number,
+                                    agent_string: agent_string.clone(),
+                                    peer_id: source.to_string(),
+                                    author: author.clone(),
+                                    topics: topics.clone(),
+                                    data: message.clone(),
+                                });
+                                mothra.client.receive_gossip(id.to_string(), sequence_number, agent_string, source.to_string(), author, topics, message.clone());
+                            }
+                            BehaviourEvent::PubsubPublished { topic, data } => {
+                                let encoding = topic.encoding();
+                                mothra.record(Direction::Outbound, None, RecordKind::Gossip { topic: topic.into(), message_id: None, sequence_number: None }, &data, encoding);
                             }
                             BehaviourEvent::PeerSubscribed(peer_id, topic) => {
                                 //debug!(mothra.log, "Subscribed to: {:?} for topic: {:?}", peer_id, topic);
                             },
+                            // banned by the libp2p service
+                            BehaviourEvent::BanPeer(..) => {}
                         }
                         Libp2pEvent::NewListenAddr(multiaddr) => {
                             mothra.network_globals.listen_multiaddrs.write().push(multiaddr);
@@ -262,18 +563,24 @@ fn spawn_mothra(mut mothra: Mothra, executor: &TaskExecutor) -> error::Result<()
 }
 
 // TODO: Consider removing these helper methods and simply using the network_send channel
+/// Publishes a message. The returned receiver resolves to an upper bound of the number of peers
+/// the message was sent to (see `Behaviour::publish`), or to the reason it was not published. It
+/// fails if the network service stopped.
 pub fn gossip(
     mut network_send: mpsc::UnboundedSender<NetworkMessage>,
     topic: String,
     data: Vec<u8>,
     log: slog::Logger,
-) {
+) -> oneshot::Receiver<Result<usize, PublishError>> {
+    let (result_send, result_recv) = oneshot::channel();
     network_send
         .send(NetworkMessage::Publish {
             topic: GossipTopic::new(topic),
             message: data,
+            result: Some(result_send),
         })
         .unwrap_or_else(|_| warn!(log, "Could not send gossip message."));
+    result_recv
 }
 
 pub fn rpc_request(
@@ -316,11 +623,243 @@ pub fn rpc_response(
         .unwrap_or_else(|_| warn!(log, "Could not send RPC response to the network service"));
 }
 
+/// Rejects the request `receive_rpc` delivered with `index`.
+pub fn rpc_error_response(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    method: String,
+    peer: String,
+    index: u8,
+    error_code: u8,
+    reason: String,
+    log: slog::Logger,
+) {
+    let error: RPCResponseErrorCode = error_code.into();
+    let peer_id = match decode_peer_id(&peer) {
+        Some(peer_id) => peer_id,
+        None => {
+            warn!(log, "Invalid peer id"; "peer" => peer);
+            return;
+        }
+    };
+    network_send
+        .send(NetworkMessage::SendErrorResponse {
+            peer_id,
+            error,
+            reason,
+            index,
+        })
+        .unwrap_or_else(|_| warn!(log, "Could not send RPC error response to the network service"));
+}
+
+pub fn dial(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    address: String,
+    log: slog::Logger,
+) {
+    let message = match dial_message(&address) {
+        Ok(message) => message,
+        Err(e) => {
+            warn!(log, "Invalid dial address"; "error" => e);
+            return;
+        }
+    };
+    network_send
+        .send(message)
+        .unwrap_or_else(|_| warn!(log, "Could not send dial request to the network service"));
+}
+
+pub fn disconnect(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    peer: String,
+    reason: u64,
+    log: slog::Logger,
+) {
+    let peer_id = match decode_peer_id(&peer) {
+        Some(peer_id) => peer_id,
+        None => {
+            warn!(log, "Invalid peer id"; "peer" => peer);
+            return;
+        }
+    };
+    network_send
+        .send(NetworkMessage::Disconnect {
+            peer_id,
+            reason: reason.into(),
+        })
+        .unwrap_or_else(|_| warn!(log, "Could not send disconnect request to the network service"));
+}
+
+/// Reports the result of the validation of a received gossip message. Valid messages are
+/// propagated, invalid ones lower the score of the peer that sent them.
+pub fn validate_gossip(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    message_id: String,
+    peer: String,
+    valid: bool,
+    log: slog::Logger,
+) {
+    let propagation_source = match decode_peer_id(&peer) {
+        Some(peer_id) => peer_id,
+        None => {
+            warn!(log, "Invalid peer id"; "peer" => peer);
+            return;
+        }
+    };
+    let message_id = MessageId(message_id);
+    let message = if valid {
+        NetworkMessage::Propagate {
+            propagation_source,
+            message_id,
+        }
+    } else {
+        NetworkMessage::Reject {
+            propagation_source,
+            message_id,
+        }
+    };
+    network_send
+        .send(message)
+        .unwrap_or_else(|_| warn!(log, "Could not send validation result to the network service"));
+}
+
+pub fn ban(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    peer: String,
+    duration_secs: u64,
+    log: slog::Logger,
+) {
+    let peer_id = match decode_peer_id(&peer) {
+        Some(peer_id) => peer_id,
+        None => {
+            warn!(log, "Invalid peer id"; "peer" => peer);
+            return;
+        }
+    };
+    network_send
+        .send(NetworkMessage::Ban {
+            peer_id,
+            duration: Duration::from_secs(duration_secs),
+        })
+        .unwrap_or_else(|_| warn!(log, "Could not send ban request to the network service"));
+}
+
+pub fn unban(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    peer: String,
+    log: slog::Logger,
+) {
+    let peer_id = match decode_peer_id(&peer) {
+        Some(peer_id) => peer_id,
+        None => {
+            warn!(log, "Invalid peer id"; "peer" => peer);
+            return;
+        }
+    };
+    network_send
+        .send(NetworkMessage::Unban { peer_id })
+        .unwrap_or_else(|_| warn!(log, "Could not send unban request to the network service"));
+}
+
+/// Adds a peer id or CIDR range to the allow list (`allow` is true) or the deny list.
+pub fn add_filter_entry(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    allow: bool,
+    entry: String,
+    log: slog::Logger,
+) {
+    let entry = match entry.parse::<FilterEntry>() {
+        Ok(entry) => entry,
+        Err(e) => {
+            warn!(log, "Invalid peer filter entry"; "error" => e);
+            return;
+        }
+    };
+    network_send
+        .send(NetworkMessage::AddFilterEntry {
+            list: filter_list(allow),
+            entry,
+        })
+        .unwrap_or_else(|_| warn!(log, "Could not send peer filter update to the network service"));
+}
+
+/// Removes a peer id or CIDR range from the allow list (`allow` is true) or the deny list.
+pub fn remove_filter_entry(
+    mut network_send: mpsc::UnboundedSender<NetworkMessage>,
+    allow: bool,
+    entry: String,
+    log: slog::Logger,
+) {
+    let entry = match entry.parse::<FilterEntry>() {
+        Ok(entry) => entry,
+        Err(e) => {
+            warn!(log, "Invalid peer filter entry"; "error" => e);
+            return;
+        }
+    };
+    network_send
+        .send(NetworkMessage::RemoveFilterEntry {
+            list: filter_list(allow),
+            entry,
+        })
+        .unwrap_or_else(|_| warn!(log, "Could not send peer filter update to the network service"));
+}
+
+fn filter_list(allow: bool) -> FilterList {
+    if allow {
+        FilterList::Allow
+    } else {
+        FilterList::Deny
+    }
+}
+
+/// Returns the protocol name and the payload of a request.
+fn request_parts(request: &Request) -> (&'static str, &[u8]) {
+    match request {
+        Request::Status(data) => ("status", data),
+        Request::Goodbye(data) => ("goodbye", data),
+    }
+}
+
+/// Returns the protocol name and the payload of a response.
+fn response_parts(response: &Response) -> (&'static str, &[u8]) {
+    match response {
+        Response::Status(data) => ("status", data),
+    }
+}
+
+/// Builds the message dialing a multiaddr or an ENR.
+pub(crate) fn dial_message(address: &str) -> Result<NetworkMessage, String> {
+    // ENRs are distinguished from multiaddrs by their "enr:" prefix
+    if address.starts_with("enr:") {
+        address
+            .parse::<Enr>()
+            .map(|enr| NetworkMessage::DialEnr { enr })
+            .map_err(|e| format!("Invalid ENR {}: {:?}", address, e))
+    } else {
+        address
+            .parse::<Multiaddr>()
+            .map(|address| NetworkMessage::Dial { address })
+            .map_err(|e| format!("Invalid Multiaddr {}: {}", address, e))
+    }
+}
+
+/// Decodes a base58 encoded peer id.
+pub(crate) fn decode_peer_id(peer: &str) -> Option<PeerId> {
+    bs58::decode(peer)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| PeerId::from_bytes(bytes).ok())
+}
+
 /// Types of messages that the network service can receive.
 #[derive(Debug)]
 pub enum NetworkMessage {
     /// Subscribe to a list of topics.
     Subscribe { subscriptions: Vec<GossipTopic> },
+    /// Subscribe to the topics matching a pattern as peers subscribe to them.
+    SubscribePattern { pattern: TopicPattern },
+    /// Unsubscribe from a pattern and the topics it subscribed to.
+    UnsubscribePattern { pattern: TopicPattern },
     /// Send an RPC request to the libp2p service.
     SendRequest {
         peer_id: PeerId,
@@ -333,16 +872,52 @@ pub enum NetworkMessage {
         response: Response,
         index: u8,
     },
+    /// Send an error Response to the libp2p service.
+    SendErrorResponse {
+        peer_id: PeerId,
+        error: RPCResponseErrorCode,
+        reason: String,
+        index: u8,
+    },
     /// Publish a message.
     Publish {
         topic: GossipTopic,
         message: Vec<u8>,
+        /// Receives an upper bound of the number of peers the message was sent to, or why it was
+        /// not published.
+        result: Option<oneshot::Sender<Result<usize, PublishError>>>,
     },
     /// Propagate a received gossipsub message.
     Propagate {
         propagation_source: PeerId,
         message_id: MessageId,
     },
-    /// Disconnect and bans a peer id.
-    Disconnect { peer_id: PeerId },
+    /// Reject a received gossipsub message, penalizing the peer that sent it.
+    Reject {
+        propagation_source: PeerId,
+        message_id: MessageId,
+    },
+    /// Dial a peer at the given multiaddr.
+    Dial { address: Multiaddr },
+    /// Add an ENR to the routing table and dial the peer it belongs to.
+    DialEnr { enr: Enr },
+    /// Send a Goodbye to a peer and disconnect it.
+    Disconnect {
+        peer_id: PeerId,
+        reason: GoodbyeReason,
+    },
+    /// Disconnect and ban a peer id for the given duration.
+    Ban { peer_id: PeerId, duration: Duration },
+    /// Lift the ban of a peer id.
+    Unban { peer_id: PeerId },
+    /// Add an entry to the allow or deny list.
+    AddFilterEntry {
+        list: FilterList,
+        entry: FilterEntry,
+    },
+    /// Remove an entry from the allow or deny list.
+    RemoveFilterEntry {
+        list: FilterList,
+        entry: FilterEntry,
+    },
 }
@@ -0,0 +1,431 @@
+//! Records the gossip and RPC traffic of the node to capture files.
+//!
+//! A capture file is a sequence of records, each prefixed by its length as a big-endian `u32` and
+//! encoded with bincode. Files are named `capture-<start time>-<index>.bin` and a new file is
+//! started once the current one reaches the configured size, so that the files of a run sort in
+//! the order they were written.
+//!
+//! Records are written by a dedicated thread, so that the network service never waits for the
+//! disk. Records are dropped when the thread falls too far behind.
+use network::GossipEncoding;
+use serde_derive::{Deserialize, Serialize};
+use slog::{debug, warn};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The extension of capture files.
+const CAPTURE_EXTENSION: &str = "bin";
+/// The number of records waiting to be written after which new records are dropped.
+const CAPTURE_QUEUE_SIZE: usize = 4096;
+
+/// Whether a message was received from or sent to a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// The kind of a recorded message.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RecordKind {
+    /// A gossipsub message. Published messages have no message id nor sequence number.
+    Gossip {
+        topic: String,
+        message_id: Option<String>,
+        sequence_number: Option<u64>,
+    },
+    /// An RPC request. Inbound requests carry the index used to respond to them.
+    RpcRequest {
+        protocol: String,
+        request_index: Option<u8>,
+    },
+    /// A successful RPC response.
+    RpcResponse { protocol: String },
+    /// An RPC error response, the data holds the reason.
+    RpcErrorResponse { error: String },
+}
+
+/// A single recorded message.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// Milliseconds since the unix epoch.
+    pub timestamp_ms: u64,
+    pub direction: Direction,
+    /// The remote peer, `None` for published gossip.
+    pub peer_id: Option<String>,
+    pub kind: RecordKind,
+    /// The message as sent or received: the encoded gossipsub data, or the RPC payload.
+    pub data: Vec<u8>,
+    /// The snappy decompressed message, if `data` is the data of a snappy encoded topic.
+    pub decompressed: Option<Vec<u8>>,
+}
+
+/// Records messages to rotating capture files from a dedicated thread.
+pub struct Recorder {
+    /// Sends the records to write along with the encoding of their data.
+    records: Option<SyncSender<(CaptureRecord, GossipEncoding)>>,
+    /// The thread writing the records, which stops once `records` is dropped.
+    writer: Option<JoinHandle<()>>,
+    log: slog::Logger,
+}
+
+impl Recorder {
+    /// Creates the capture directory if needed, opens the first capture file and starts the
+    /// thread writing to it. Messages that decompress to more than `max_decompressed_size` bytes
+    /// are recorded without their decompressed data.
+    pub fn new(
+        dir: PathBuf,
+        max_file_size: u64,
+        max_decompressed_size: usize,
+        log: slog::Logger,
+    ) -> io::Result<Self> {
+        let mut writer = CaptureWriter::new(dir, max_file_size, max_decompressed_size)?;
+        debug!(log, "Recording traffic"; "dir" => format!("{:?}", writer.dir));
+        let (records, pending) = mpsc::sync_channel(CAPTURE_QUEUE_SIZE);
+        let writer_log = log.clone();
+        let writer = thread::Builder::new()
+            .name("recorder".into())
+            .spawn(move || {
+                for (record, encoding) in pending {
+                    if let Err(e) = writer.write(record, encoding) {
+                        warn!(writer_log, "Could not record message"; "error" => e.to_string());
+                    }
+                }
+            })?;
+        Ok(Recorder {
+            records: Some(records),
+            writer: Some(writer),
+            log,
+        })
+    }
+
+    /// Records a message whose data is encoded with `encoding`. Failures are logged and do not
+    /// affect the node.
+    pub fn record(
+        &mut self,
+        direction: Direction,
+        peer_id: Option<String>,
+        kind: RecordKind,
+        data: &[u8],
+        encoding: GossipEncoding,
+    ) {
+        let record = CaptureRecord {
+            timestamp_ms: now_ms(),
+            direction,
+            peer_id,
+            kind,
+            data: data.to_vec(),
+            decompressed: None,
+        };
+        let records = match self.records.as_ref() {
+            Some(records) => records,
+            None => return,
+        };
+        match records.try_send((record, encoding)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                debug!(self.log, "Dropping record, the capture queue is full");
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                warn!(self.log, "Capture writer stopped, not recording message");
+            }
+        }
+    }
+}
+
+impl Drop for Recorder {
+    /// Waits for the pending records to be written.
+    fn drop(&mut self) {
+        self.records = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Writes records to rotating capture files.
+struct CaptureWriter {
+    /// The directory the capture files are written to.
+    dir: PathBuf,
+    /// The size after which a new capture file is started.
+    max_file_size: u64,
+    /// The maximum size of the decompressed data of a record.
+    max_decompressed_size: usize,
+    /// Start time of the recorder, shared by all its files.
+    started: u64,
+    /// Index of the current file.
+    file_index: u32,
+    file: BufWriter<File>,
+    file_size: u64,
+}
+
+impl CaptureWriter {
+    fn new(dir: PathBuf, max_file_size: u64, max_decompressed_size: usize) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let started = now_ms();
+        let file = create_capture_file(&dir, started, 0)?;
+        Ok(CaptureWriter {
+            dir,
+            max_file_size,
+            max_decompressed_size,
+            started,
+            file_index: 0,
+            file,
+            file_size: 0,
+        })
+    }
+
+    fn write(&mut self, mut record: CaptureRecord, encoding: GossipEncoding) -> io::Result<()> {
+        if encoding == GossipEncoding::SSZSnappy {
+            // the data may be invalid or claim a huge size, decoding checks it first
+            record.decompressed = encoding
+                .decode(record.data.clone(), self.max_decompressed_size)
+                .ok();
+        }
+
+        let bytes = bincode::serialize(&record)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        let record_size = 4 + bytes.len() as u64;
+
+        if self.file_size > 0 && self.file_size + record_size > self.max_file_size {
+            self.file.flush()?;
+            self.file_index += 1;
+            self.file = create_capture_file(&self.dir, self.started, self.file_index)?;
+            self.file_size = 0;
+        }
+
+        self.file.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        self.file.write_all(&bytes)?;
+        // captures are mostly read after a crash or an incident, keep them complete
+        self.file.flush()?;
+        self.file_size += record_size;
+        Ok(())
+    }
+}
+
+/// Iterates over the records of a capture.
+pub struct CaptureReader<R> {
+    reader: R,
+    /// Set once the end of the capture or an error is reached.
+    done: bool,
+}
+
+impl CaptureReader<BufReader<File>> {
+    /// Opens a capture file.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(CaptureReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(reader: R) -> Self {
+        CaptureReader {
+            reader,
+            done: false,
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<CaptureRecord>, String> {
+        let mut len_bytes = [0u8; 4];
+        let mut read = 0;
+        while read < len_bytes.len() {
+            match self.reader.read(&mut len_bytes[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err("Capture ends within a record length".into()),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(format!("Could not read capture: {}", e)),
+            }
+        }
+
+        let mut bytes = vec![0u8; u32::from_be_bytes(len_bytes) as usize];
+        self.reader
+            .read_exact(&mut bytes)
+            .map_err(|e| format!("Could not read capture record: {}", e))?;
+        bincode::deserialize(&bytes)
+            .map(Some)
+            .map_err(|e| format!("Invalid capture record: {}", e))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Returns the capture files of a directory, in the order they were written.
+pub fn capture_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_capture = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| name.starts_with("capture-"))
+            && path
+                .extension()
+                .map_or(false, |ext| ext == CAPTURE_EXTENSION);
+        if is_capture {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Iterates over all the records of the capture files of a directory.
+pub fn read_captures(
+    dir: &Path,
+) -> io::Result<impl Iterator<Item = Result<CaptureRecord, String>>> {
+    let readers = capture_files(dir)?
+        .into_iter()
+        .map(|path| CaptureReader::open(&path))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(readers.into_iter().flatten())
+}
+
+fn create_capture_file(dir: &Path, started: u64, index: u32) -> io::Result<BufWriter<File>> {
+    let path = dir.join(format!(
+        "capture-{}-{:05}.{}",
+        started, index, CAPTURE_EXTENSION
+    ));
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::{o, Discard};
+    use tempdir::TempDir;
+
+    fn gossip(topic: &str) -> RecordKind {
+        RecordKind::Gossip {
+            topic: topic.into(),
+            message_id: None,
+            sequence_number: None,
+        }
+    }
+
+    fn recorder(dir: &TempDir, max_file_size: u64) -> Recorder {
+        Recorder::new(
+            dir.path().to_path_buf(),
+            max_file_size,
+            1024,
+            slog::Logger::root(Discard, o!()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_record_and_read() {
+        let dir = TempDir::new("captures").unwrap();
+        let mut recorder = recorder(&dir, 1024);
+
+        let compressed = snap::raw::Encoder::new().compress_vec(b"hello").unwrap();
+        let snappy = GossipEncoding::SSZSnappy;
+        recorder.record(
+            Direction::Outbound,
+            None,
+            gossip("topic"),
+            &compressed,
+            snappy,
+        );
+        recorder.record(
+            Direction::Inbound,
+            Some("peer".into()),
+            RecordKind::RpcRequest {
+                protocol: "status".into(),
+                request_index: Some(3),
+            },
+            &[1, 2, 3],
+            GossipEncoding::Raw,
+        );
+        // a snappy header claiming about 4 GB is not decompressed
+        let oversized = [0xf0, 0xff, 0xff, 0xff, 0x0f, 0x00];
+        recorder.record(
+            Direction::Inbound,
+            None,
+            gossip("topic"),
+            &oversized,
+            snappy,
+        );
+        // waits for the records to be written
+        drop(recorder);
+
+        let records: Vec<CaptureRecord> = read_captures(dir.path())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].kind, gossip("topic"));
+        assert_eq!(records[0].data, compressed);
+        assert_eq!(records[0].decompressed, Some(b"hello".to_vec()));
+        assert_eq!(records[1].direction, Direction::Inbound);
+        assert_eq!(records[1].peer_id, Some("peer".into()));
+        assert_eq!(records[1].data, vec![1, 2, 3]);
+        assert_eq!(records[1].decompressed, None);
+        assert_eq!(records[2].data, oversized.to_vec());
+        assert_eq!(records[2].decompressed, None);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = TempDir::new("captures").unwrap();
+        let mut recorder = recorder(&dir, 100);
+
+        for i in 0..10u8 {
+            let data = [i; 40];
+            recorder.record(
+                Direction::Outbound,
+                None,
+                gossip("t"),
+                &data,
+                GossipEncoding::Raw,
+            );
+        }
+        drop(recorder);
+
+        assert!(capture_files(dir.path()).unwrap().len() > 1);
+        let data: Vec<Vec<u8>> = read_captures(dir.path())
+            .unwrap()
+            .map(|record| record.unwrap().data)
+            .collect();
+        assert_eq!(data, (0..10u8).map(|i| vec![i; 40]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_truncated_capture() {
+        let mut bytes = 10u32.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[0; 4]);
+        let mut reader = CaptureReader::new(&bytes[..]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,349 @@
+//! WebSocket JSON-RPC 2.0 API to drive the node without linking the library.
+//!
+//! Methods:
+//! - `publish { topic, data }`: publishes hex encoded `data` on `topic`.
+//! - `subscribe { topic }`: subscribes to `topic` and streams its messages to this connection as
+//!   `gossip` notifications, which list every topic the message was published on and the author
+//!   of signed messages. A topic containing `*` is a pattern, which subscribes to the matching
+//!   topics peers subscribe to.
+//! - `rpc_request { peer_id, data }`: sends a Status request to a peer.
+//! - `rpc_respond { peer_id, request_index, data }`: responds to a request received through an
+//!   `rpc` notification.
+//! - `peers`: lists the connected peers.
+//! - `dial { address }`: dials a multiaddr or an ENR.
+//!
+//! RPC messages received from peers are sent to every connection as `rpc` notifications.
+use crate::error;
+use crate::mothra::{decode_peer_id, dial_message, NetworkMessage};
+use futures::prelude::*;
+use network::rpc::RequestId;
+use network::{GossipTopic, NetworkGlobals, PeerId, Request, Response, TaskExecutor, TopicPattern};
+use serde_json::{json, Value};
+use slog::{debug, info, o, warn};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, RecvError};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+/// The number of notifications buffered for a connection before it starts missing some.
+pub const NOTIFICATION_CAPACITY: usize = 1024;
+
+/* JSON-RPC 2.0 error codes */
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Events of the network service forwarded to the WebSocket connections.
+#[derive(Clone, Debug)]
+pub enum WsNotification {
+    /// A gossipsub message was received.
+    Gossip {
+        message_id: String,
+        sequence_number: u64,
+        agent_string: String,
+        peer_id: String,
+        author: Option<String>,
+        topics: Vec<String>,
+        data: Vec<u8>,
+    },
+    /// An RPC message was received. `request_index` identifies requests to respond to.
+    Rpc {
+        method: String,
+        req_resp: u8,
+        peer_id: String,
+        request_index: Option<u8>,
+        data: Vec<u8>,
+    },
+}
+
+/// Starts the WebSocket server on `listen_address`. The server stops when the executor exits.
+pub fn serve(
+    executor: &TaskExecutor,
+    listen_address: SocketAddr,
+    network_send: mpsc::UnboundedSender<NetworkMessage>,
+    network_globals: Arc<NetworkGlobals>,
+    notifications: broadcast::Sender<WsNotification>,
+    log: slog::Logger,
+) -> error::Result<()> {
+    // bind synchronously so that an unavailable address is reported on startup
+    let listener = std::net::TcpListener::bind(listen_address)
+        .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+        .map_err(|e| format!("Unable to bind WebSocket API to {}: {}", listen_address, e))?;
+    info!(log, "WebSocket API started"; "address" => format!("ws://{}", listen_address));
+
+    let connection_executor = executor.clone();
+    executor.spawn(
+        async move {
+            let mut listener = match TcpListener::from_std(listener) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    warn!(log, "WebSocket API failed"; "error" => e.to_string());
+                    return;
+                }
+            };
+            loop {
+                match listener.accept().await {
+                    Ok((stream, address)) => {
+                        let connection = Connection {
+                            network_send: network_send.clone(),
+                            network_globals: network_globals.clone(),
+                            notifications: notifications.subscribe(),
+                            topics: HashSet::new(),
+                            patterns: vec![],
+                            log: log.new(o!("ws_client" => address.to_string())),
+                        };
+                        connection_executor.spawn(connection.run(stream), "ws_api_connection");
+                    }
+                    Err(e) => {
+                        warn!(log, "Could not accept WebSocket connection"; "error" => e.to_string())
+                    }
+                }
+            }
+        },
+        "ws_api",
+    );
+    Ok(())
+}
+
+/// A single WebSocket client.
+struct Connection {
+    network_send: mpsc::UnboundedSender<NetworkMessage>,
+    network_globals: Arc<NetworkGlobals>,
+    notifications: broadcast::Receiver<WsNotification>,
+    /// The topics this client subscribed to.
+    topics: HashSet<String>,
+    /// The topic patterns this client subscribed to.
+    patterns: Vec<TopicPattern>,
+    log: slog::Logger,
+}
+
+impl Connection {
+    async fn run(mut self, stream: TcpStream) {
+        let mut ws = match tokio_tungstenite::accept_async(stream).await {
+            Ok(ws) => ws,
+            Err(e) => {
+                debug!(self.log, "WebSocket handshake failed"; "error" => e.to_string());
+                return;
+            }
+        };
+        debug!(self.log, "WebSocket client connected");
+
+        loop {
+            let outgoing = tokio::select! {
+                message = ws.next() => match message {
+                    Some(Ok(Message::Text(text))) => Some(self.handle_request(&text)),
+                    // pings are answered by tungstenite, binary frames are not part of the API
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => None,
+                    Some(Err(e)) => {
+                        debug!(self.log, "WebSocket error"; "error" => e.to_string());
+                        break;
+                    }
+                },
+                notification = self.notifications.recv() => match notification {
+                    Ok(notification) => self.notification_text(notification),
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!(self.log, "WebSocket client is too slow, notifications dropped"; "missed" => missed);
+                        None
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            };
+
+            if let Some(text) = outgoing {
+                if let Err(e) = ws.send(Message::Text(text)).await {
+                    debug!(self.log, "WebSocket error"; "error" => e.to_string());
+                    break;
+                }
+            }
+        }
+        debug!(self.log, "WebSocket client disconnected");
+    }
+
+    /// Handles a JSON-RPC request and returns the serialized response.
+    fn handle_request(&mut self, text: &str) -> String {
+        let request: Value = match serde_json::from_str(text) {
+            Ok(request) => request,
+            Err(e) => return error_response(Value::Null, PARSE_ERROR, e.to_string()),
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = match request.get("method").and_then(Value::as_str) {
+            Some(method) => method,
+            None => return error_response(id, INVALID_REQUEST, "Missing method".into()),
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+        match self.call(method, &params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string(),
+            Err((code, message)) => error_response(id, code, message),
+        }
+    }
+
+    fn call(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "publish" => {
+                let topic = str_param(params, "topic")?;
+                let message = hex_param(params, "data")?;
+                self.send(NetworkMessage::Publish {
+                    topic: GossipTopic::new(topic),
+                    message,
+                    result: None,
+                })?;
+            }
+            "subscribe" => {
+                let topic = str_param(params, "topic")?;
+                if TopicPattern::is_pattern(&topic) {
+                    let pattern = TopicPattern::new(topic).map_err(|e| (INVALID_PARAMS, e))?;
+                    self.send(NetworkMessage::SubscribePattern {
+                        pattern: pattern.clone(),
+                    })?;
+                    self.patterns.push(pattern);
+                } else {
+                    let gossip_topic = GossipTopic::new(topic.clone());
+                    gossip_topic.validate().map_err(|e| (INVALID_PARAMS, e))?;
+                    self.send(NetworkMessage::Subscribe {
+                        subscriptions: vec![gossip_topic],
+                    })?;
+                    self.topics.insert(topic);
+                }
+            }
+            "rpc_request" => {
+                let peer_id = peer_id_param(params, "peer_id")?;
+                let data = hex_param(params, "data")?;
+                self.send(NetworkMessage::SendRequest {
+                    peer_id,
+                    request: Request::Status(data),
+                    request_id: RequestId::Behaviour,
+                })?;
+            }
+            "rpc_respond" => {
+                let peer_id = peer_id_param(params, "peer_id")?;
+                let index = params
+                    .get("request_index")
+                    .and_then(Value::as_u64)
+                    .filter(|index| *index <= u8::max_value() as u64)
+                    .ok_or_else(|| invalid_params("request_index"))?;
+                let data = hex_param(params, "data")?;
+                self.send(NetworkMessage::SendResponse {
+                    peer_id,
+                    response: Response::Status(data),
+                    index: index as u8,
+                })?;
+            }
+            "peers" => {
+                let peers: Vec<String> = self
+                    .network_globals
+                    .peers
+                    .read()
+                    .connected_peer_ids()
+                    .map(|peer_id| peer_id.to_string())
+                    .collect();
+                return Ok(json!(peers));
+            }
+            "dial" => {
+                let address = str_param(params, "address")?;
+                let message = dial_message(&address).map_err(|e| (INVALID_PARAMS, e))?;
+                self.send(message)?;
+            }
+            _ => return Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+        }
+        Ok(Value::Bool(true))
+    }
+
+    fn send(&mut self, message: NetworkMessage) -> Result<(), (i64, String)> {
+        self.network_send.send(message).map_err(|_| {
+            (
+                INTERNAL_ERROR,
+                "Could not send the message to the network service".to_string(),
+            )
+        })
+    }
+
+    /// Serializes a notification, if this client is interested in it.
+    fn notification_text(&self, notification: WsNotification) -> Option<String> {
+        let (method, params) = match notification {
+            WsNotification::Gossip {
+                message_id,
+                sequence_number,
+                agent_string,
+                peer_id,
+                author,
+                topics,
+                data,
+            } => {
+                // `topic` is the first topic of the message this client subscribed to
+                let topic = topics.iter().find(|topic| {
+                    self.topics.contains(*topic)
+                        || self.patterns.iter().any(|pattern| pattern.matches(topic))
+                })?;
+                let params = json!({
+                    "message_id": message_id,
+                    "sequence_number": sequence_number,
+                    "agent_string": agent_string,
+                    "peer_id": peer_id,
+                    "author": author,
+                    "topic": topic,
+                    "topics": topics,
+                    "data": hex::encode(data),
+                });
+                ("gossip", params)
+            }
+            WsNotification::Rpc {
+                method,
+                req_resp,
+                peer_id,
+                request_index,
+                data,
+            } => {
+                let params = json!({
+                    "method": method,
+                    "req_resp": req_resp,
+                    "peer_id": peer_id,
+                    "request_index": request_index,
+                    "data": hex::encode(data),
+                });
+                ("rpc", params)
+            }
+        };
+        Some(json!({ "jsonrpc": "2.0", "method": method, "params": params }).to_string())
+    }
+}
+
+fn error_response(id: Value, code: i64, message: String) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+    .to_string()
+}
+
+fn invalid_params(name: &str) -> (i64, String) {
+    (
+        INVALID_PARAMS,
+        format!("Missing or invalid parameter: {}", name),
+    )
+}
+
+fn str_param(params: &Value, name: &str) -> Result<String, (i64, String)> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or_else(|| invalid_params(name))
+}
+
+fn hex_param(params: &Value, name: &str) -> Result<Vec<u8>, (i64, String)> {
+    let value = str_param(params, name)?;
+    hex::decode(value.trim_start_matches("0x")).map_err(|_| invalid_params(name))
+}
+
+fn peer_id_param(params: &Value, name: &str) -> Result<PeerId, (i64, String)> {
+    let value = str_param(params, name)?;
+    decode_peer_id(&value).ok_or_else(|| invalid_params(name))
+}
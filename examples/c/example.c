@@ -20,12 +20,15 @@ void on_discovered_peer(const unsigned char* peer_utf8, int peer_length) {
     printf(",peer=%.*s\n", peer_length, peer_utf8);
 }
 
-void on_receive_gossip(const unsigned char* message_id_utf8, int message_id_length, const unsigned char* peer_id_utf8, int peer_id_length, const unsigned char* topic_utf8, int topic_length, unsigned char* data, int data_length) {
+void on_receive_gossip(const unsigned char* message_id_utf8, int message_id_length, const unsigned char* peer_id_utf8, int peer_id_length, const unsigned char* author_utf8, int author_length, unsigned long long sequence_number, const unsigned char** topics_utf8, const int* topic_lengths, int topics_count, unsigned char* data, int data_length) {
     printf("C: received gossip");
     printf(",message_id=%.*s", message_id_length, message_id_utf8);
     printf(",peer_id=%.*s", peer_id_length, peer_id_utf8);
-    printf(",topic=%.*s", topic_length, topic_utf8);
-    printf(",topic=%.*s", topic_length, topic_utf8);
+    printf(",author=%.*s", author_length, author_utf8);
+    printf(",sequence_number=%llu", sequence_number);
+    for (int i = 0; i < topics_count; i++) {
+        printf(",topic=%.*s", topic_lengths[i], topics_utf8[i]);
+    }
     printf(",data=%.*s\n", data_length, data);
 }
 
@@ -36,6 +39,14 @@ void on_receive_rpc(const unsigned char* method_utf8, int method_length, int req
     printf(",data=%.*s\n", data_length, data);
 }
 
+void on_publish_result(unsigned long long publish_id, int result) {
+    if (result < 0) {
+        printf("C: could not publish message %llu, error=%i\n", publish_id, result);
+    } else {
+        printf("C: published message %llu to at most %i peers\n", publish_id, result);
+    }
+}
+
 int main (int argc, char** argv) {
 
     char* client_constants[3] = {
@@ -48,6 +59,7 @@ int main (int argc, char** argv) {
         on_receive_gossip,
         on_receive_rpc
     );
+    register_publish_result_handler(on_publish_result);
     network_start((char**)client_constants,LEN(client_constants),argv,argc);
     srand(time(NULL));
     while(1){
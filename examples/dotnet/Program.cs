@@ -164,14 +164,19 @@ namespace Example
             Console.WriteLine($" discovered {peer}");
         }
 
-        public unsafe void OnReceiveGossip(byte* messageIdUtf8, int messageIdLength, byte* peerIdUtf8, int peerIdLength, byte* topicUtf8, int topicLength, byte* data, int dataLength)
+        public unsafe void OnReceiveGossip(byte* messageIdUtf8, int messageIdLength, byte* peerIdUtf8, int peerIdLength, byte* authorUtf8, int authorLength, ulong sequenceNumber, byte** topicsUtf8, int* topicLengths, int topicsCount, byte* data, int dataLength)
         {
             Console.Write("dotnet: receive");
             string messageId = new String((sbyte*)messageIdUtf8, 0, messageIdLength, Encoding.UTF8);
             string peerId = new String((sbyte*)peerIdUtf8, 0, peerIdLength, Encoding.UTF8);
-            string topic = new String((sbyte*)topicUtf8, 0, topicLength, Encoding.UTF8);
+            string author = new String((sbyte*)authorUtf8, 0, authorLength, Encoding.UTF8);
+            string[] topics = new string[topicsCount];
+            for (int i = 0; i < topicsCount; i++)
+            {
+                topics[i] = new String((sbyte*)topicsUtf8[i], 0, topicLengths[i], Encoding.UTF8);
+            }
             string dataString = new String((sbyte*)data, 0, dataLength, Encoding.UTF8);
-            Console.WriteLine($"messageId={messageId},peerId={peerId},gossip={topic},data={dataString}");
+            Console.WriteLine($"messageId={messageId},peerId={peerId},author={author},sequenceNumber={sequenceNumber},gossip={string.Join(",", topics)},data={dataString}");
         }
 
         public unsafe void OnReceiveRpc(byte* methodUtf8, int methodLength, int requestResponseFlag, byte* peerUtf8,
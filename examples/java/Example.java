@@ -51,8 +51,8 @@ public class Example {
         return true;
     }
 
-    public static Boolean printGossipMessage(String topic, byte[] message){
-        System.out.println("Java: received a gossip message. " + topic + ":" + new String(message));
+    public static Boolean printGossipMessage(String[] topics, byte[] message){
+        System.out.println("Java: received a gossip message. " + String.join(",", topics) + ":" + new String(message));
         return true;
     }
 
@@ -3,7 +3,7 @@ use clap::{App, AppSettings, Arg, ArgMatches};
 use env_logger::Env;
 use mothra::{cli_app, gossip, Mothra, NetworkMessage, Subscriber, TaskExecutor};
 use slog::{debug, info, o, trace, warn, Drain, Level, Logger};
-use std::{thread, time};
+use std::{process, thread, time};
 use tokio::runtime::Runtime;
 use tokio::{signal, sync::mpsc, task};
 
@@ -23,11 +23,13 @@ impl Subscriber for Client {
         println!("peer={:?}", peer);
     }
 
-    fn receive_gossip(&self, message_id: String, sequence_number: u64, agent_string: String, peer_id: String, topic: String, data: Vec<u8>) {
+    fn receive_gossip(&self, message_id: String, sequence_number: u64, agent_string: String, peer_id: String, author: Option<String>, topics: Vec<String>, data: Vec<u8>) {
         println!("Rust: received gossip");
         println!("message id={:?}", message_id);
         println!("peer id={:?}", peer_id);
-        println!("topic={:?}", topic);
+        println!("author={:?}", author);
+        println!("sequence number={:?}", sequence_number);
+        println!("topics={:?}", topics);
         println!("data={:?}", String::from_utf8_lossy(&data));
     }
 
@@ -62,12 +64,24 @@ fn main() {
         println!("Foo flag found");
     }
 
+    let mothra_matches = matches.subcommand_matches("mothra").unwrap();
     let config = Mothra::get_config(
         Some("rust-example".into()),
         Some(format!("v{}-unstable", env!("CARGO_PKG_VERSION"))),
         Some("rust-example/libp2p".into()),
-        &matches.subcommand_matches("mothra").unwrap(),
+        mothra_matches,
     );
+    match config.requested_dump(mothra_matches) {
+        Some(Ok(dump)) => {
+            println!("{}", dump);
+            process::exit(0);
+        }
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        None => {}
+    }
     // configure logging
     env_logger::Builder::from_env(Env::default()).init();
     let decorator = slog_term::TermDecorator::new().build();
@@ -87,7 +101,7 @@ fn main() {
     let enr_fork_id = [0u8; 32].to_vec();
     let meta_data = [0u8; 32].to_vec();
     let ping_data = [0u8; 32].to_vec();
-    let client = Box::new(Client::new()) as Box<dyn Subscriber + Send>;
+    let client = Box::new(Client::new()) as Box<dyn Subscriber + Send + Sync>;
     let mut runtime = Runtime::new()
         .map_err(|e| format!("Failed to start runtime: {:?}", e))
         .unwrap();
@@ -117,7 +131,12 @@ fn main() {
                 let data = format!("Hello from Rust.  Elapsed time: {:?}", start.elapsed())
                     .as_bytes()
                     .to_vec();
-                gossip(network_send.clone(), topic, data, mothra_log.clone());
+                let result = gossip(network_send.clone(), topic, data, mothra_log.clone());
+                match futures::executor::block_on(result) {
+                    Ok(Ok(peers)) => debug!(mothra_log, "Published message"; "peers" => peers),
+                    Ok(Err(e)) => warn!(mothra_log, "Could not publish message"; "error" => e.to_string()),
+                    Err(_) => warn!(mothra_log, "Network service stopped"),
+                }
             }
         });
         // block the current thread until SIGINT is received.
@@ -0,0 +1,362 @@
+//! Re-publishes the gossip messages and RPC requests of a traffic capture into a network.
+//!
+//! Records are replayed in the order they were captured, preserving their relative timing scaled
+//! by `--rate`. Gossip messages are published decoded on their original topic, Status requests are
+//! sent to every peer given with `--targets`. Other RPC requests, which the application cannot
+//! send, are skipped. The envelopes of captured messages (time to live,
+//! signature, encryption) are part of the replayed data, so captures of nodes using them should be
+//! replayed into networks that do not.
+use clap::{App, Arg, ArgMatches};
+use mothra::recorder::{read_captures, CaptureReader, CaptureRecord, RecordKind};
+use mothra::{
+    cli_app, gossip, rpc_request, Mothra, MothraPeerId, NetworkMessage, Subscriber, TaskExecutor,
+};
+use slog::{info, o, warn, Drain, Level, Logger};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::{process, thread};
+use tokio::runtime::Runtime;
+use tokio::{signal, sync::mpsc, task};
+
+/// Replay options given on the command line.
+struct ReplayConfig {
+    /// Speed multiplier of the replay, 0 replays without delays.
+    rate: f64,
+    /// The peers RPC requests are sent to.
+    targets: Vec<String>,
+    /// The number of connected peers to wait for before replaying.
+    min_peers: usize,
+    /// How long to wait for `min_peers`.
+    peer_timeout: Duration,
+}
+
+/// The replay only publishes, received messages are ignored.
+struct Replayer;
+
+impl Subscriber for Replayer {
+    fn init(&mut self, _network_send: mpsc::UnboundedSender<NetworkMessage>, _fork_id: Vec<u8>) {}
+
+    fn discovered_peer(&self, _peer: String) {}
+
+    fn receive_gossip(
+        &self,
+        _message_id: String,
+        _sequence_number: u64,
+        _agent_string: String,
+        _peer_id: String,
+        _author: Option<String>,
+        _topics: Vec<String>,
+        _data: Vec<u8>,
+    ) {
+    }
+
+    fn receive_rpc(&self, _method: String, _req_resp: u8, _peer: String, _data: Vec<u8>) {}
+}
+
+fn main() {
+    let matches = App::new("mothra-replay")
+        .version(clap::crate_version!())
+        .about("Re-publishes a Mothra traffic capture into a network")
+        .arg(
+            Arg::with_name("capture")
+                .value_name("CAPTURE")
+                .help("A capture file, or a directory whose capture files are replayed in order.")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rate")
+                .long("rate")
+                .value_name("RATE")
+                .help("Speed multiplier of the replay (2 replays twice as fast). 0 replays without delays.")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("targets")
+                .long("targets")
+                .value_name("PEER-IDS")
+                .help("Comma-delimited peer ids the recorded Status requests are sent to. RPC requests are skipped when not set.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("min-peers")
+                .long("min-peers")
+                .value_name("COUNT")
+                .help("The number of connected peers to wait for before replaying.")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("peer-timeout")
+                .long("peer-timeout")
+                .value_name("SECONDS")
+                .help("How long to wait for --min-peers before replaying anyway.")
+                .default_value("60")
+                .takes_value(true),
+        )
+        .subcommand(cli_app())
+        .get_matches();
+
+    let replay_config = parse_replay_config(&matches).unwrap_or_else(|e| exit_with(&e));
+    let records = load_records(Path::new(matches.value_of("capture").unwrap_or_default()))
+        .unwrap_or_else(|e| exit_with(&e));
+    let (records, skipped_requests) = replayable_records(records);
+
+    let mothra_matches = match matches.subcommand_matches("mothra") {
+        Some(mothra_matches) => mothra_matches.clone(),
+        None => cli_app().get_matches_from(vec!["mothra"]),
+    };
+    let config = Mothra::get_config(
+        Some("mothra-replay".into()),
+        Some(format!("v{}-unstable", env!("CARGO_PKG_VERSION"))),
+        Some("mothra-replay/libp2p".into()),
+        &mothra_matches,
+    );
+    if let Some(dump) = config.requested_dump(&mothra_matches) {
+        println!("{}", dump.unwrap_or_else(|e| exit_with(&e)));
+        return;
+    }
+
+    let decorator = slog_term::TermDecorator::new().build();
+    let drain = slog_term::CompactFormat::new(decorator).build().fuse();
+    let drain = slog_async::Async::new(drain).build();
+    let drain = match config.debug_level.as_str() {
+        "debug" => drain.filter_level(Level::Debug),
+        "trace" => drain.filter_level(Level::Trace),
+        "warn" => drain.filter_level(Level::Warning),
+        "error" => drain.filter_level(Level::Error),
+        "crit" => drain.filter_level(Level::Critical),
+        _ => drain.filter_level(Level::Info),
+    };
+    let log = Logger::root(drain.fuse(), o!("service" => "replay"));
+    if skipped_requests > 0 {
+        warn!(log, "Skipping RPC requests other than Status"; "count" => skipped_requests);
+    }
+
+    let mut runtime = Runtime::new().unwrap_or_else(|e| exit_with(&e.to_string()));
+    let (network_exit_signal, exit) = exit_future::signal();
+    let task_executor = TaskExecutor::new(
+        runtime.handle().clone(),
+        exit,
+        log.new(o!("service" => "executor")),
+    );
+    let replay_log = log.clone();
+    runtime.block_on(async move {
+        let replay = task::spawn_blocking(move || {
+            let (network_globals, network_send) = match Mothra::new(
+                config,
+                [0u8; 32].to_vec(),
+                [0u8; 32].to_vec(),
+                [0u8; 32].to_vec(),
+                &task_executor,
+                Box::new(Replayer),
+                replay_log.new(o!("service" => "mothra")),
+            ) {
+                Ok(network) => network,
+                Err(e) => {
+                    warn!(replay_log, "Could not start Mothra"; "error" => e.to_string());
+                    return;
+                }
+            };
+
+            let waiting = Instant::now();
+            while network_globals.connected_peers() < replay_config.min_peers {
+                if waiting.elapsed() > replay_config.peer_timeout {
+                    warn!(replay_log, "Not enough peers, replaying anyway";
+                        "connected" => network_globals.connected_peers());
+                    break;
+                }
+                thread::sleep(Duration::from_secs(1));
+            }
+
+            replay(records, &replay_config, network_send, &replay_log);
+        });
+
+        // stop on SIGINT or once everything was replayed
+        tokio::select! {
+            _ = replay => {}
+            _ = signal::ctrl_c() => {}
+        }
+    });
+
+    let _ = network_exit_signal.fire();
+    runtime.shutdown_timeout(Duration::from_millis(300));
+}
+
+fn parse_replay_config(matches: &ArgMatches) -> Result<ReplayConfig, String> {
+    let rate_str = matches.value_of("rate").unwrap_or("1");
+    let rate = rate_str
+        .parse::<f64>()
+        .ok()
+        .filter(|rate| *rate >= 0.0)
+        .ok_or_else(|| format!("Invalid rate: {}", rate_str))?;
+
+    let mut targets = vec![];
+    if let Some(targets_str) = matches.value_of("targets") {
+        for target in targets_str.split(',') {
+            MothraPeerId::from_str(target)
+                .map_err(|_| format!("Invalid target peer id: {}", target))?;
+            targets.push(target.to_string());
+        }
+    }
+
+    let min_peers_str = matches.value_of("min-peers").unwrap_or("1");
+    let min_peers = min_peers_str
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid min peers: {}", min_peers_str))?;
+
+    let peer_timeout_str = matches.value_of("peer-timeout").unwrap_or("60");
+    let peer_timeout = peer_timeout_str
+        .parse::<u64>()
+        .map(Duration::from_secs)
+        .map_err(|_| format!("Invalid peer timeout: {}", peer_timeout_str))?;
+
+    Ok(ReplayConfig {
+        rate,
+        targets,
+        min_peers,
+        peer_timeout,
+    })
+}
+
+/// Reads the records of a capture file or directory.
+fn load_records(path: &Path) -> Result<Vec<CaptureRecord>, String> {
+    if path.is_dir() {
+        read_captures(path)
+            .map_err(|e| format!("Could not read captures in {:?}: {}", path, e))?
+            .collect()
+    } else {
+        CaptureReader::open(path)
+            .map_err(|e| format!("Could not open capture {:?}: {}", path, e))?
+            .collect()
+    }
+}
+
+/// Keeps the gossip messages and the Status requests, the only requests `rpc_request` sends.
+/// Returns them with the number of other requests skipped.
+fn replayable_records(records: Vec<CaptureRecord>) -> (Vec<CaptureRecord>, usize) {
+    let mut skipped_requests = 0;
+    let records = records
+        .into_iter()
+        .filter(|record| match &record.kind {
+            RecordKind::Gossip { .. } => true,
+            RecordKind::RpcRequest { protocol, .. } if protocol == "status" => true,
+            RecordKind::RpcRequest { .. } => {
+                skipped_requests += 1;
+                false
+            }
+            RecordKind::RpcResponse { .. } | RecordKind::RpcErrorResponse { .. } => false,
+        })
+        .collect();
+    (records, skipped_requests)
+}
+
+fn replay(
+    records: Vec<CaptureRecord>,
+    replay_config: &ReplayConfig,
+    network_send: mpsc::UnboundedSender<NetworkMessage>,
+    log: &Logger,
+) {
+    let first_timestamp = match records.first() {
+        Some(record) => record.timestamp_ms,
+        None => {
+            warn!(log, "Nothing to replay");
+            return;
+        }
+    };
+    info!(log, "Replaying capture"; "records" => records.len(), "rate" => replay_config.rate);
+
+    let started = Instant::now();
+    let (mut gossip_count, mut rpc_count) = (0, 0);
+    for record in records {
+        if replay_config.rate > 0.0 {
+            let offset = record.timestamp_ms.saturating_sub(first_timestamp);
+            let due = Duration::from_millis(offset).div_f64(replay_config.rate);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+
+        match record.kind {
+            RecordKind::Gossip { topic, .. } => {
+                // captures hold the data as encoded for the topic, publishing encodes it again
+                let data = record.decompressed.unwrap_or(record.data);
+                gossip(network_send.clone(), topic, data, log.clone());
+                gossip_count += 1;
+            }
+            RecordKind::RpcRequest { protocol, .. } => {
+                for target in &replay_config.targets {
+                    rpc_request(
+                        network_send.clone(),
+                        protocol.clone(),
+                        target.clone(),
+                        record.data.clone(),
+                        log.clone(),
+                    );
+                    rpc_count += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    info!(log, "Replay complete"; "gossip" => gossip_count, "rpc_requests" => rpc_count,
+        "duration" => format!("{:?}", started.elapsed()));
+    // give the network service time to send the last messages
+    thread::sleep(Duration::from_secs(1));
+}
+
+fn exit_with(error: &str) -> ! {
+    eprintln!("{}", error);
+    process::exit(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mothra::recorder::Direction;
+
+    fn record(kind: RecordKind) -> CaptureRecord {
+        CaptureRecord {
+            timestamp_ms: 0,
+            direction: Direction::Inbound,
+            peer_id: None,
+            kind,
+            data: vec![1, 2, 3],
+            decompressed: None,
+        }
+    }
+
+    fn request(protocol: &str) -> CaptureRecord {
+        record(RecordKind::RpcRequest {
+            protocol: protocol.into(),
+            request_index: Some(0),
+        })
+    }
+
+    #[test]
+    fn test_replayable_records() {
+        let gossip = record(RecordKind::Gossip {
+            topic: "/mothra/topic".into(),
+            message_id: None,
+            sequence_number: None,
+        });
+        let records = vec![
+            gossip.clone(),
+            request("status"),
+            request("goodbye"),
+            record(RecordKind::RpcResponse {
+                protocol: "status".into(),
+            }),
+            record(RecordKind::RpcErrorResponse {
+                error: "Server Error".into(),
+            }),
+        ];
+
+        let (replayed, skipped_requests) = replayable_records(records);
+        assert_eq!(replayed, vec![gossip, request("status")]);
+        assert_eq!(skipped_requests, 1);
+    }
+}